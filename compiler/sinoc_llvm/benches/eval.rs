@@ -0,0 +1,61 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use inkwell::context::Context;
+
+use sinoc_llvm::Session;
+
+/// Benchmarks `Session::run` on each of the given inputs, in a single session
+/// where the variable `x` is defined.
+fn bench_inputs(c: &mut Criterion, group_name: &str, inputs: &[(&str, String)]) {
+    let context = Context::create();
+    let mut session = Session::new(&context);
+
+    session.run("x = 1").unwrap();
+
+    let mut group = c.benchmark_group(group_name);
+
+    for (name, input) in inputs {
+        group.bench_with_input(BenchmarkId::from_parameter(name), input, |b, input| {
+            b.iter(|| session.run(input).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+/// Short expressions, either made of literals only, which are not compiled, or using a variable.
+fn literal_math(c: &mut Criterion) {
+    let inputs = [
+        ("constant", "2 + 3 * 4".to_string()),
+        ("mixed", "(1 + 2) * (3 - 4) / 5 ** 2 % 7".to_string()),
+        ("variable", "x + 3 * 4".to_string()),
+    ];
+
+    bench_inputs(c, "literal_math", &inputs);
+}
+
+/// Deeply nested parenthesized expressions.
+fn nested_parens(c: &mut Criterion) {
+    let nested = |depth| format!("{}x{}", "(".repeat(depth), " + 1)".repeat(depth));
+    let inputs = [
+        ("depth_10", nested(10)),
+        ("depth_50", nested(50)),
+        ("depth_150", nested(150)),
+    ];
+
+    bench_inputs(c, "nested_parens", &inputs);
+}
+
+/// Long chains of binary operators.
+fn operator_chains(c: &mut Criterion) {
+    let chain = |len| format!("x{}", " + x * 2".repeat(len));
+    let inputs = [
+        ("length_10", chain(10)),
+        ("length_100", chain(100)),
+        ("length_1000", chain(1000)),
+    ];
+
+    bench_inputs(c, "operator_chains", &inputs);
+}
+
+criterion_group!(benches, literal_math, nested_parens, operator_chains);
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+//! Feeds arbitrary inputs to the lexer, the parser and the compiler, which must
+//! report errors rather than panic. Inputs are not run, since a fuzzed program
+//! may loop forever or call an unresolved external function.
+//!
+//! Run it from `compiler/sinoc_llvm` with `cargo +nightly fuzz run compile`.
+//! A crashing input is saved under `fuzz/artifacts/compile/`, and can be
+//! reproduced with `cargo +nightly fuzz run compile <path-to-artifact>`.
+
+#![no_main]
+
+use inkwell::context::Context;
+use libfuzzer_sys::fuzz_target;
+
+use sinoc_llvm::Session;
+
+fuzz_target!(|input: &str| {
+    let context = Context::create();
+    let mut session = Session::new(&context);
+
+    let _ = session.ir(input);
+});
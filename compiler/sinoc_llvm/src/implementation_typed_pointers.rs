@@ -1,241 +1,52 @@
 use std::borrow::Borrow;
 use std::collections::HashMap;
-use std::iter::Peekable;
-use std::ops::DerefMut;
-use std::str::Chars;
+use std::fmt;
+use std::ops::Range;
 
 use inkwell::builder::Builder;
 use inkwell::context::Context;
+use inkwell::intrinsics::Intrinsic;
 use inkwell::module::Module;
 use inkwell::types::BasicMetadataTypeEnum;
 use inkwell::values::{
-    BasicMetadataValueEnum, BasicValueEnum, FloatValue, FunctionValue, PointerValue,
+    BasicMetadataValueEnum, BasicValueEnum, FloatValue, FunctionValue, GlobalValue, IntValue,
+    PointerValue,
 };
-use inkwell::FloatPredicate;
+use inkwell::{AddressSpace, FloatPredicate};
 
 // use inkwell_internals::llvm_versions;
 
-use crate::Token::*;
+use crate::lexer::Token::{self, *};
+use crate::lexer::{LexError, Lexer};
+use crate::session::{BINARY_OPERATORS, LAST_RESULT};
 
 const ANONYMOUS_FUNCTION_NAME: &str = "anonymous";
 
-// ======================================================================================
-// LEXER ================================================================================
-// ======================================================================================
-
-/// Represents a primitive syntax token.
-#[derive(Debug, Clone)]
-pub enum Token {
-    Binary,
-    Comma,
-    Comment,
-    Def,
-    Else,
-    EOF,
-    Extern,
-    For,
-    Ident(String),
-    If,
-    In,
-    LParen,
-    Number(f64),
-    Op(char),
-    RParen,
-    Then,
-    Unary,
-    Var,
-}
-
-/// Defines an error encountered by the `Lexer`.
-pub struct LexError {
-    pub error: &'static str,
-    pub index: usize,
-}
-
-impl LexError {
-    #[allow(unused)]
-    pub fn new(msg: &'static str) -> LexError {
-        LexError {
-            error: msg,
-            index: 0,
-        }
-    }
-
-    #[allow(unused)]
-    pub fn with_index(msg: &'static str, index: usize) -> LexError {
-        LexError { error: msg, index }
-    }
-}
-
-/// Defines the result of a lexing operation; namely a
-/// `Token` on success, or a `LexError` on failure.
-pub type LexResult = Result<Token, LexError>;
-
-/// Defines a lexer which transforms an input `String` into
-/// a `Token` stream.
-pub struct Lexer<'a> {
-    input: &'a str,
-    chars: Box<Peekable<Chars<'a>>>,
-    pos: usize,
-}
-
-impl<'a> Lexer<'a> {
-    /// Creates a new `Lexer`, given its source `input`.
-    pub fn new(input: &'a str) -> Lexer<'a> {
-        Lexer {
-            input,
-            chars: Box::new(input.chars().peekable()),
-            pos: 0,
-        }
-    }
-
-    /// Lexes and returns the next `Token` from the source code.
-    pub fn lex(&mut self) -> LexResult {
-        let chars = self.chars.deref_mut();
-        let src = self.input;
-
-        let mut pos = self.pos;
-
-        // Skip whitespaces
-        loop {
-            // Note: the following lines are in their own scope to
-            // limit how long 'chars' is borrowed, and in order to allow
-            // it to be borrowed again in the loop by 'chars.next()'.
-            {
-                let ch = chars.peek();
-
-                if ch.is_none() {
-                    self.pos = pos;
-
-                    return Ok(Token::EOF);
-                }
-
-                if !ch.unwrap().is_whitespace() {
-                    break;
-                }
-            }
-
-            chars.next();
-            pos += 1;
-        }
-
-        let start = pos;
-        let next = chars.next();
-
-        if next.is_none() {
-            return Ok(Token::EOF);
-        }
-
-        pos += 1;
-
-        // Actually get the next token.
-        let result = match next.unwrap() {
-            '(' => Ok(Token::LParen),
-            ')' => Ok(Token::RParen),
-            ',' => Ok(Token::Comma),
-
-            '#' => {
-                // Comment
-                loop {
-                    let ch = chars.next();
-                    pos += 1;
-
-                    if ch == Some('\n') {
-                        break;
-                    }
-                }
-
-                Ok(Token::Comment)
-            }
-
-            '.' | '0'..='9' => {
-                // Parse number literal
-                loop {
-                    let ch = match chars.peek() {
-                        Some(ch) => *ch,
-                        None => return Ok(Token::EOF),
-                    };
-
-                    // Parse float.
-                    if ch != '.' && !ch.is_ascii_hexdigit() {
-                        break;
-                    }
-
-                    chars.next();
-                    pos += 1;
-                }
-
-                Ok(Token::Number(src[start..pos].parse().unwrap()))
-            }
-
-            'a'..='z' | 'A'..='Z' | '_' => {
-                // Parse identifier
-                loop {
-                    let ch = match chars.peek() {
-                        Some(ch) => *ch,
-                        None => return Ok(Token::EOF),
-                    };
+/// Built-in binary operators that associate to the right.
+const RIGHT_ASSOCIATIVE_OPERATORS: &[&str] = &["**"];
 
-                    // A word-like identifier only contains underscores and alphanumeric characters.
-                    if ch != '_' && !ch.is_alphanumeric() {
-                        break;
-                    }
-
-                    chars.next();
-                    pos += 1;
-                }
-
-                match &src[start..pos] {
-                    "def" => Ok(Token::Def),
-                    "extern" => Ok(Token::Extern),
-                    "if" => Ok(Token::If),
-                    "then" => Ok(Token::Then),
-                    "else" => Ok(Token::Else),
-                    "for" => Ok(Token::For),
-                    "in" => Ok(Token::In),
-                    "unary" => Ok(Token::Unary),
-                    "binary" => Ok(Token::Binary),
-                    "var" => Ok(Token::Var),
-
-                    ident => Ok(Token::Ident(ident.to_string())),
-                }
-            }
-
-            op => {
-                // Parse operator
-                Ok(Token::Op(op))
-            }
-        };
-
-        // Update stored position, and return
-        self.pos = pos;
+/// Built-in comparison operators, which chain as in 'a < b < c'.
+const COMPARISON_OPERATORS: &[&str] = &["<", ">", "<=", ">=", "==", "!="];
 
-        result
-    }
-}
+/// Name given to the call of the built-in postfix factorial operator.
+pub(crate) const FACTORIAL_FUNCTION_NAME: &str = "postfix!";
 
-impl<'a> Iterator for Lexer<'a> {
-    type Item = Token;
+/// Largest number whose factorial is finite; the factorial of larger numbers is an infinity.
+pub(crate) const MAX_FACTORIAL_OPERAND: f64 = 170.0;
 
-    /// Lexes the next `Token` and returns it.
-    /// On EOF or failure, `None` will be returned.
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.lex() {
-            Ok(EOF) | Err(_) => None,
-            Ok(token) => Some(token),
-        }
-    }
-}
+/// Maximum nesting depth of parsed expressions, beyond which the recursive descent
+/// could overflow the native stack. Python's parser uses the same limit.
+const MAX_NESTING_DEPTH: usize = 200;
 
 // ======================================================================================
 // PARSER ===============================================================================
 // ======================================================================================
 
 /// Defines a primitive expression.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Expr {
     Binary {
-        op: char,
+        op: String,
         left: Box<Expr>,
         right: Box<Expr>,
     },
@@ -270,27 +81,63 @@ pub enum Expr {
 }
 
 /// Defines the prototype (name and parameters) of a function.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Prototype {
     pub name: String,
     pub args: Vec<String>,
+    #[allow(unused)]
     pub is_op: bool,
+    #[allow(unused)]
     pub prec: usize,
 }
 
 /// Defines a user-defined or external function.
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct Function {
     pub prototype: Prototype,
     pub body: Option<Expr>,
     pub is_anon: bool,
 }
 
+/// Defines an error encountered by the `Parser`, along with the index
+/// of the character it was encountered at, and the byte range of the
+/// offending token.
+#[derive(Debug)]
+pub struct ParseError {
+    pub error: &'static str,
+    pub index: usize,
+    pub span: Range<usize>,
+}
+
+impl From<LexError> for ParseError {
+    fn from(err: LexError) -> Self {
+        ParseError {
+            error: err.error,
+            index: err.index,
+            span: err.span,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at column {})", self.error, self.index + 1)
+    }
+}
+
 /// Represents the `Expr` parser.
 pub struct Parser<'a> {
     tokens: Vec<Token>,
+    indices: Vec<usize>,
+    spans: Vec<Range<usize>>,
+    eof_index: usize,
+    eof_offset: usize,
+    lex_error: Option<LexError>,
     pos: usize,
-    prec: &'a mut HashMap<char, i32>,
+    depth: usize,
+    chained: usize,
+    percent: bool,
+    prec: &'a mut HashMap<String, i32>,
 }
 
 // I'm ignoring the 'must_use' lint in order to call 'self.advance' without checking
@@ -299,31 +146,81 @@ pub struct Parser<'a> {
 impl<'a> Parser<'a> {
     /// Creates a new parser, given an input `str` and a `HashMap` binding
     /// an operator and its precedence in binary expressions.
-    pub fn new(input: String, op_precedence: &'a mut HashMap<char, i32>) -> Self {
+    pub fn new(input: String, op_precedence: &'a mut HashMap<String, i32>) -> Self {
         let mut lexer = Lexer::new(input.as_str());
-        let tokens = lexer.by_ref().collect();
+        let mut tokens = vec![];
+        let mut indices = vec![];
+        let mut spans = vec![];
+        let mut lex_error = None;
+
+        loop {
+            match lexer.lex() {
+                Ok(EOF) => break,
+                Ok(Comment) => (),
+                Ok(token) => {
+                    tokens.push(token);
+                    indices.push(lexer.index());
+                    spans.push(lexer.span());
+                }
+                Err(err) => {
+                    lex_error = Some(err);
+                    break;
+                }
+            }
+        }
 
         Parser {
             tokens,
+            indices,
+            spans,
+            eof_index: input.trim_end().chars().count(),
+            eof_offset: input.trim_end().len(),
+            lex_error,
             prec: op_precedence,
             pos: 0,
+            depth: 0,
+            chained: 0,
+            percent: false,
         }
     }
 
+    /// Makes a '%' that is not followed by an operand a postfix operator dividing by 100,
+    /// so that `200 * 50%` is `100`.
+    pub fn with_percent(mut self) -> Self {
+        self.percent = true;
+        self
+    }
+
     /// Parses the content of the parser.
-    pub fn parse(&mut self) -> Result<Function, &'static str> {
+    pub fn parse(&mut self) -> Result<Function, ParseError> {
+        if let Some(err) = self.lex_error.take() {
+            return Err(err.into());
+        }
+
+        self.parse_function().map_err(|error| ParseError {
+            error,
+            index: self.index(),
+            span: self.span(),
+        })
+    }
+
+    /// Parses a single definition, external declaration or top-level expression.
+    fn parse_function(&mut self) -> Result<Function, &'static str> {
         let result = match self.current()? {
             Def => self.parse_def(),
             Extern => self.parse_extern(),
+            RParen => Err("Unmatched ')'."),
             _ => self.parse_toplevel_expr(),
         };
 
         match result {
             Ok(result) => {
-                if !self.at_end() {
-                    Err("Unexpected token after parsed expression.")
-                } else {
+                if self.at_end() {
                     Ok(result)
+                } else if matches!(self.current(), Ok(RParen)) {
+                    Err("Unmatched ')'.")
+                } else {
+                    Err("Unexpected token after parsed expression.")
                 }
             }
 
@@ -361,12 +258,49 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// Returns the index of the character the current `Token` starts at,
+    /// or the index of the end of the input if it has been reached.
+    fn index(&self) -> usize {
+        self.indices
+            .get(self.pos)
+            .copied()
+            .unwrap_or(self.eof_index)
+    }
+
+    /// Returns the byte range of the current `Token`, or an empty range
+    /// at the end of the input if it has been reached.
+    fn span(&self) -> Range<usize> {
+        self.spans
+            .get(self.pos)
+            .cloned()
+            .unwrap_or(self.eof_offset..self.eof_offset)
+    }
+
     /// Returns a value indicating whether or not the `Parser`
     /// has reached the end of the input.
     fn at_end(&self) -> bool {
         self.pos >= self.tokens.len()
     }
 
+    /// Runs the given parsing function one nesting level deeper,
+    /// failing once the maximum nesting depth is exceeded.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, &'static str>,
+    ) -> Result<T, &'static str> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err("Maximum recursion depth exceeded.");
+        }
+
+        self.depth += 1;
+
+        let result = parse(self);
+
+        self.depth -= 1;
+
+        result
+    }
+
     /// Returns the precedence of the current `Token`, or 0 if it is not recognized as a binary operator.
     fn get_tok_precedence(&self) -> i32 {
         if let Ok(Op(op)) = self.current() {
@@ -378,7 +312,7 @@ impl<'a> Parser<'a> {
 
     /// Parses the prototype of a function, whether external or user-defined.
     fn parse_prototype(&mut self) -> Result<Prototype, &'static str> {
-        let (id, is_operator, precedence) = match self.curr() {
+        let (id, is_operator, precedence) = match self.current()? {
             Ident(id) => {
                 self.advance()?;
 
@@ -389,7 +323,7 @@ impl<'a> Parser<'a> {
                 self.advance()?;
 
                 let op = match self.curr() {
-                    Op(ch) => ch,
+                    Op(op) => op,
                     _ => return Err("Expected operator in custom operator declaration."),
                 };
 
@@ -397,7 +331,7 @@ impl<'a> Parser<'a> {
 
                 let mut name = String::from("binary");
 
-                name.push(op);
+                name.push_str(&op);
 
                 let prec = if let Number(prec) = self.curr() {
                     self.advance()?;
@@ -416,13 +350,13 @@ impl<'a> Parser<'a> {
                 self.advance()?;
 
                 let op = match self.curr() {
-                    Op(ch) => ch,
+                    Op(op) => op,
                     _ => return Err("Expected operator in custom operator declaration."),
                 };
 
                 let mut name = String::from("unary");
 
-                name.push(op);
+                name.push_str(&op);
 
                 self.advance()?;
 
@@ -466,7 +400,7 @@ impl<'a> Parser<'a> {
                     break;
                 }
                 Comma => {
-                    self.advance();
+                    self.advance()?;
                 }
                 _ => return Err("Expected ',' or ')' character in prototype declaration."),
             }
@@ -516,9 +450,18 @@ impl<'a> Parser<'a> {
 
     /// Parses any expression.
     fn parse_expr(&mut self) -> Result<Expr, &'static str> {
-        match self.parse_unary_expr() {
-            Ok(left) => self.parse_binary_expr(0, left),
-            err => err,
+        let left = self.parse_unary_expr()?;
+
+        self.parse_expr_rest(left)
+    }
+
+    /// Parses the rest of an expression, given its first operand.
+    fn parse_expr_rest(&mut self, left: Expr) -> Result<Expr, &'static str> {
+        let expr = self.parse_binary_expr(0, left)?;
+
+        match self.current() {
+            Ok(If) => self.nested(|parser| parser.parse_postfix_conditional_expr(expr)),
+            _ => Ok(expr),
         }
     }
 
@@ -535,24 +478,47 @@ impl<'a> Parser<'a> {
     }
 
     /// Parses an expression enclosed in parenthesis.
+    ///
+    /// A run of opening parentheses is consumed at once, and the expressions they enclose
+    /// are parsed from the innermost one outwards in a loop, so that redundant parentheses
+    /// as in `((((5))))` do not recurse nor count towards the nesting depth.
     fn parse_paren_expr(&mut self) -> Result<Expr, &'static str> {
         match self.current()? {
             LParen => (),
             _ => return Err("Expected '(' character at start of parenthesized expression."),
         }
 
-        self.advance()?;
+        let mut open = 0;
 
-        let expr = self.parse_expr()?;
+        while let Ok(LParen) = self.current() {
+            self.advance()?;
+            open += 1;
+        }
 
-        match self.current()? {
-            RParen => (),
-            _ => return Err("Expected ')' character at end of parenthesized expression."),
+        if let RParen = self.current()? {
+            return Err("Empty parentheses.");
         }
 
-        self.advance();
+        let mut expr = self.parse_expr()?;
+
+        loop {
+            match self.current()? {
+                RParen => (),
+                _ => return Err("Expected ')' character at end of parenthesized expression."),
+            }
 
-        Ok(expr)
+            self.advance();
+            open -= 1;
+
+            if open == 0 {
+                return Ok(expr);
+            }
+
+            // the enclosed expression is the first operand of the enclosing one
+            let left = self.parse_postfix_ops(expr)?;
+
+            expr = self.parse_expr_rest(left)?;
+        }
     }
 
     /// Parses an expression that starts with an identifier (either a variable or a function call).
@@ -571,6 +537,8 @@ impl<'a> Parser<'a> {
                 self.advance()?;
 
                 if let RParen = self.curr() {
+                    self.advance();
+
                     return Ok(Expr::Call {
                         fn_name: id,
                         args: vec![],
@@ -603,30 +571,79 @@ impl<'a> Parser<'a> {
     /// Parses an unary expression.
     fn parse_unary_expr(&mut self) -> Result<Expr, &'static str> {
         let op = match self.current()? {
-            Op(ch) => {
+            Op(op) => {
                 self.advance()?;
-                ch
+                op
             }
-            _ => return self.parse_primary(),
+            _ => return self.nested(Self::parse_postfix_expr),
         };
 
         let mut name = String::from("unary");
 
-        name.push(op);
+        name.push_str(&op);
 
         Ok(Expr::Call {
             fn_name: name,
-            args: vec![self.parse_unary_expr()?],
+            args: vec![self.nested(Self::parse_unary_expr)?],
         })
     }
 
+    /// Parses a primary expression followed by any number of postfix '!' (factorial) operators,
+    /// and of postfix '%' (percent) ones when enabled, which bind more tightly than unary
+    /// and binary operators.
+    fn parse_postfix_expr(&mut self) -> Result<Expr, &'static str> {
+        let expr = self.parse_primary()?;
+
+        self.parse_postfix_ops(expr)
+    }
+
+    /// Parses any number of postfix operators following the given primary expression.
+    fn parse_postfix_ops(&mut self, mut expr: Expr) -> Result<Expr, &'static str> {
+        loop {
+            match self.current() {
+                Ok(Op(ref op)) if op == "!" => {
+                    self.advance();
+
+                    expr = Expr::Call {
+                        fn_name: FACTORIAL_FUNCTION_NAME.to_string(),
+                        args: vec![expr],
+                    };
+                }
+
+                // a '%' followed by an operand stays the modulo operator
+                Ok(Op(ref op)) if op == "%" && self.percent && !self.precedes_operand() => {
+                    self.advance();
+
+                    expr = Expr::Binary {
+                        op: "/".to_string(),
+                        left: Box::new(expr),
+                        right: Box::new(Expr::Number(100.0)),
+                    };
+                }
+
+                _ => break Ok(expr),
+            }
+        }
+    }
+
+    /// Returns whether the token following the current one starts a primary expression.
+    fn precedes_operand(&self) -> bool {
+        matches!(
+            self.tokens.get(self.pos + 1),
+            Some(Ident(_) | Number(_) | LParen | If | For | Var)
+        )
+    }
+
     /// Parses a binary expression, given its left-hand expression.
     fn parse_binary_expr(&mut self, prec: i32, mut left: Expr) -> Result<Expr, &'static str> {
+        // comparisons following 'left', as in 'left < b < c'
+        let mut chain = Vec::new();
+
         loop {
             let curr_prec = self.get_tok_precedence();
 
             if curr_prec < prec || self.at_end() {
-                return Ok(left);
+                return Ok(self.chain_comparisons(left, chain));
             }
 
             let op = match self.curr() {
@@ -634,24 +651,109 @@ impl<'a> Parser<'a> {
                 _ => return Err("Invalid operator."),
             };
 
-            self.advance()?;
+            // an operator ending the input, as in '5 /', lacks its right-hand operand
+            self.advance()
+                .map_err(|_| "Expected an operand after binary operator.")?;
 
             let mut right = self.parse_unary_expr()?;
 
             let next_prec = self.get_tok_precedence();
 
-            if curr_prec < next_prec {
-                right = self.parse_binary_expr(curr_prec + 1, right)?;
+            if RIGHT_ASSOCIATIVE_OPERATORS.contains(&op.as_str()) {
+                if curr_prec <= next_prec {
+                    right = self.nested(|parser| parser.parse_binary_expr(curr_prec, right))?;
+                }
+            } else if curr_prec < next_prec {
+                right = self.nested(|parser| parser.parse_binary_expr(curr_prec + 1, right))?;
+            }
+
+            if COMPARISON_OPERATORS.contains(&op.as_str()) {
+                chain.push((op, right));
+                continue;
             }
 
             left = Expr::Binary {
                 op,
-                left: Box::new(left),
+                left: Box::new(self.chain_comparisons(left, std::mem::take(&mut chain))),
                 right: Box::new(right),
             };
         }
     }
 
+    /// Builds the comparisons of `first` with the operands that follow it in `chain`.
+    /// A chain such as 'a < b < c' means 'a < b and b < c', except that each operand
+    /// is evaluated once, from left to right, and only while the comparisons hold.
+    fn chain_comparisons(&mut self, first: Expr, chain: Vec<(String, Expr)>) -> Expr {
+        let mut chain = chain.into_iter();
+
+        let (op, right) = match chain.next() {
+            Some(comparison) => comparison,
+            None => return first,
+        };
+
+        if chain.as_slice().is_empty() {
+            return Expr::Binary {
+                op,
+                left: Box::new(first),
+                right: Box::new(right),
+            };
+        }
+
+        // bind the first operand too, so that it is evaluated before the second one
+        let name = self.chained_name();
+        let body = self.chain_operands(Expr::Variable(name.clone()), op, right, &mut chain);
+
+        Expr::VarIn {
+            variables: vec![(name, Some(first))],
+            body: Box::new(body),
+        }
+    }
+
+    /// Builds the comparison of `left` with `right`, followed by the rest of the `chain`.
+    fn chain_operands(
+        &mut self,
+        left: Expr,
+        op: String,
+        right: Expr,
+        chain: &mut std::vec::IntoIter<(String, Expr)>,
+    ) -> Expr {
+        let (next_op, next_right) = match chain.next() {
+            Some(comparison) => comparison,
+            None => {
+                return Expr::Binary {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }
+        };
+
+        // 'right' is also the left operand of the next comparison
+        let name = self.chained_name();
+        let rest = self.chain_operands(Expr::Variable(name.clone()), next_op, next_right, chain);
+
+        Expr::VarIn {
+            variables: vec![(name.clone(), Some(right))],
+            body: Box::new(Expr::Binary {
+                op: "and".to_string(),
+                left: Box::new(Expr::Binary {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(Expr::Variable(name)),
+                }),
+                right: Box::new(rest),
+            }),
+        }
+    }
+
+    /// Returns a new name for a variable holding an operand of a chain of comparisons,
+    /// which cannot clash with an identifier.
+    fn chained_name(&mut self) -> String {
+        self.chained += 1;
+
+        format!("chained.{}", self.chained)
+    }
+
     /// Parses a conditional if..then..else expression.
     fn parse_conditional_expr(&mut self) -> Result<Expr, &'static str> {
         // eat 'if' token
@@ -682,6 +784,32 @@ impl<'a> Parser<'a> {
         })
     }
 
+    /// Parses a conditional ..if..else expression, given the expression it evaluates to when
+    /// its condition holds. It binds more loosely than any binary operator, including '='.
+    fn parse_postfix_conditional_expr(&mut self, then: Expr) -> Result<Expr, &'static str> {
+        // eat 'if' token
+        self.advance()?;
+
+        // the condition cannot be a conditional itself, so that 'a if b else c if d else e'
+        // nests in the alternative
+        let left = self.parse_unary_expr()?;
+        let cond = self.parse_binary_expr(0, left)?;
+
+        // eat 'else' token
+        match self.current() {
+            Ok(Else) => self.advance()?,
+            _ => return Err("Expected 'else' keyword."),
+        }
+
+        let otherwise = self.parse_expr()?;
+
+        Ok(Expr::Conditional {
+            cond: Box::new(cond),
+            consequence: Box::new(then),
+            alternative: Box::new(otherwise),
+        })
+    }
+
     /// Parses a loop for..in.. expression.
     fn parse_for_expr(&mut self) -> Result<Expr, &'static str> {
         // eat 'for' token
@@ -697,7 +825,7 @@ impl<'a> Parser<'a> {
 
         // eat '=' token
         match self.curr() {
-            Op('=') => self.advance()?,
+            Op(ref op) if op == "=" => self.advance()?,
             _ => return Err("Expected '=' character in for loop."),
         }
 
@@ -757,7 +885,7 @@ impl<'a> Parser<'a> {
 
             // read (optional) initializer
             let initializer = match self.curr() {
-                Op('=') => Some({
+                Op(ref op) if op == "=" => Some({
                     self.advance()?;
                     self.parse_expr()?
                 }),
@@ -767,7 +895,7 @@ impl<'a> Parser<'a> {
 
             variables.push((name, initializer));
 
-            match self.curr() {
+            match self.current()? {
                 Comma => {
                     self.advance()?;
                 }
@@ -821,16 +949,157 @@ impl<'a> Parser<'a> {
     }
 }
 
+/// Renders the given expression back to source, parenthesizing every operation,
+/// so that `2 + 3 * 4` gives `(2 + (3 * 4))`. The result parses back to the same
+/// expression, except for chained comparisons, whose hidden bindings have names
+/// that cannot be written.
+pub fn pretty(expr: &Expr) -> String {
+    match *expr {
+        Expr::Number(nb) => nb.to_string(),
+        Expr::Variable(ref name) => name.clone(),
+
+        Expr::Binary {
+            ref op,
+            ref left,
+            ref right,
+        } => format!("({} {} {})", pretty(left), op, pretty(right)),
+
+        Expr::Call {
+            ref fn_name,
+            ref args,
+        } => match (fn_name.strip_prefix("unary"), args.as_slice()) {
+            (Some("not"), [operand]) => format!("(not {})", pretty(operand)),
+            (Some(op), [operand]) if is_unary_op_name(fn_name) => {
+                format!("({}{})", op, pretty(operand))
+            }
+            _ if fn_name == FACTORIAL_FUNCTION_NAME => format!("({}!)", pretty(&args[0])),
+            _ => {
+                let args: Vec<String> = args.iter().map(pretty).collect();
+
+                format!("{}({})", fn_name, args.join(", "))
+            }
+        },
+
+        Expr::Conditional {
+            ref cond,
+            ref consequence,
+            ref alternative,
+        } => format!(
+            "(if {} then {} else {})",
+            pretty(cond),
+            pretty(consequence),
+            pretty(alternative)
+        ),
+
+        Expr::For {
+            ref var_name,
+            ref start,
+            ref end,
+            ref step,
+            ref body,
+        } => {
+            let step = match step {
+                Some(step) => format!(", {}", pretty(step)),
+                None => String::new(),
+            };
+
+            format!(
+                "(for {} = {}, {}{} in {})",
+                var_name,
+                pretty(start),
+                pretty(end),
+                step,
+                pretty(body)
+            )
+        }
+
+        Expr::VarIn {
+            ref variables,
+            ref body,
+        } => {
+            let variables: Vec<String> = variables
+                .iter()
+                .map(|(name, init)| match init {
+                    Some(init) => format!("{} = {}", name, pretty(init)),
+                    None => name.clone(),
+                })
+                .collect();
+
+            format!("(var {} in {})", variables.join(", "), pretty(body))
+        }
+    }
+}
+
 // ======================================================================================
 // COMPILER =============================================================================
 // ======================================================================================
 
+/// Name of the runtime function behind the `rand` built-in function, which is
+/// given the state of the random number generator and the exclusive upper bound.
+pub(crate) const RANDOM_FUNCTION_NAME: &str = "sino.rand";
+
+/// Name of the global variable holding the state of the random number generator.
+pub(crate) const RANDOM_STATE_NAME: &str = "sino.rand_state";
+
+/// Declares the runtime function behind the `rand` built-in function and the state
+/// it is given in the given module, unless they already are.
+pub(crate) fn declare_random<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+) -> (GlobalValue<'ctx>, FunctionValue<'ctx>) {
+    let state = module
+        .get_global(RANDOM_STATE_NAME)
+        .unwrap_or_else(|| module.add_global(context.i64_type(), None, RANDOM_STATE_NAME));
+
+    let function = module
+        .get_function(RANDOM_FUNCTION_NAME)
+        .unwrap_or_else(|| {
+            let f64_type = context.f64_type();
+            let state_type = context.i64_type().ptr_type(AddressSpace::default());
+            let fn_type = f64_type.fn_type(&[state_type.into(), f64_type.into()], false);
+
+            module.add_function(RANDOM_FUNCTION_NAME, fn_type, None)
+        });
+
+    (state, function)
+}
+
+/// Returns a value indicating whether or not the given function name is the one
+/// the `Parser` gives to a unary operator.
+pub(crate) fn is_unary_op_name(name: &str) -> bool {
+    name.strip_prefix("unary")
+        .and_then(|op| op.chars().next())
+        .is_some_and(|ch| ch != '_' && !ch.is_alphanumeric())
+}
+
+/// Returns a value indicating whether or not the given operator is a built-in binary operator.
+pub(crate) fn is_binary_op_name(op: &str) -> bool {
+    BINARY_OPERATORS.iter().any(|&(name, _)| name == op)
+}
+
+/// Returns the value of the built-in constant of the given name, `pi` or `e`, which
+/// local variables may shadow but global ones may not.
+pub(crate) fn builtin_constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" => Some(std::f64::consts::PI),
+        "e" => Some(std::f64::consts::E),
+        _ => None,
+    }
+}
+
+/// Returns a value indicating whether or not the given amount, once truncated
+/// to an integer, is a valid shift count from 0 to 63.
+pub(crate) fn is_shift_count(amount: f64) -> bool {
+    amount > -1.0 && amount < 64.0
+}
+
 /// Defines the `Expr` compiler.
 pub struct Compiler<'a, 'ctx> {
     pub context: &'ctx Context,
     pub builder: &'a Builder<'ctx>,
     pub module: &'a Module<'ctx>,
     pub function: &'a Function,
+    pub globals: &'a HashMap<String, Box<f64>>,
 
     variables: HashMap<String, PointerValue<'ctx>>,
     fn_value_opt: Option<FunctionValue<'ctx>>,
@@ -843,6 +1112,22 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
         self.module.get_function(name)
     }
 
+    /// Gets a pointer to a global variable given its name, declaring it in the module if needed.
+    /// Unknown variables are only created when `define` is set.
+    fn get_global(&self, name: &str, define: bool) -> Option<PointerValue<'ctx>> {
+        if let Some(global) = self.module.get_global(name) {
+            return Some(global.as_pointer_value());
+        }
+
+        if !(define || self.globals.contains_key(name)) || self.get_function(name).is_some() {
+            return None;
+        }
+
+        let global = self.module.add_global(self.context.f64_type(), None, name);
+
+        Some(global.as_pointer_value())
+    }
+
     /// Returns the `FunctionValue` representing the function being compiled.
     #[inline]
     fn fn_value(&self) -> FunctionValue<'ctx> {
@@ -880,9 +1165,15 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
         match *expr {
             Expr::Number(nb) => Ok(self.context.f64_type().const_float(nb)),
 
-            Expr::Variable(ref name) => match self.variables.get(name.as_str()) {
-                Some(var) => Ok(self.build_load(*var, name.as_str()).into_float_value()),
-                None => Err("Could not find a matching variable."),
+            Expr::Variable(ref name) => match self.variables.get(name.as_str()).copied() {
+                Some(var) => Ok(self.build_load(var, name.as_str()).into_float_value()),
+                None => match builtin_constant(name) {
+                    Some(nb) => Ok(self.context.f64_type().const_float(nb)),
+                    None => match self.get_global(name, false) {
+                        Some(var) => Ok(self.build_load(var, name.as_str()).into_float_value()),
+                        None => Err("Could not find a matching variable."),
+                    },
+                },
             },
 
             Expr::VarIn {
@@ -891,7 +1182,7 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
             } => {
                 let mut old_bindings = Vec::new();
 
-                for &(ref var_name, ref initializer) in variables {
+                for (var_name, initializer) in variables {
                     let var_name = var_name.as_str();
 
                     let initial_val = match *initializer {
@@ -921,11 +1212,11 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
             }
 
             Expr::Binary {
-                op,
+                ref op,
                 ref left,
                 ref right,
             } => {
-                if op == '=' {
+                if op == "=" {
                     // handle assignment
                     let var_name = match *left.borrow() {
                         Expr::Variable(ref var_name) => var_name,
@@ -935,56 +1226,234 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
                     };
 
                     let var_val = self.compile_expr(right)?;
+
+                    // assigning the last result discards the value, unless a local shadows it
+                    if var_name == LAST_RESULT && !self.variables.contains_key(LAST_RESULT) {
+                        return Ok(var_val);
+                    }
+
+                    if !self.variables.contains_key(var_name.as_str())
+                        && builtin_constant(var_name).is_some()
+                    {
+                        return Err("Cannot assign to a built-in constant.");
+                    }
+
+                    // top-level assignments define global variables
                     let var = self
                         .variables
                         .get(var_name.as_str())
+                        .copied()
+                        .or_else(|| self.get_global(var_name, self.function.is_anon))
                         .ok_or("Undefined variable.")?;
 
-                    self.builder.build_store(*var, var_val).unwrap();
+                    self.builder.build_store(var, var_val).unwrap();
 
                     Ok(var_val)
+                } else if op == "and" || op == "or" {
+                    // short-circuit: the right-hand side is only evaluated when the left-hand
+                    // side does not decide the result
+                    let parent = self.fn_value();
+                    let zero_const = self.context.f64_type().const_float(0.0);
+
+                    let lhs = self.compile_expr(left)?;
+                    let lhs = self
+                        .builder
+                        .build_float_compare(FloatPredicate::ONE, lhs, zero_const, "lhsbool")
+                        .unwrap();
+                    let lhs_bb = self.builder.get_insert_block().unwrap();
+
+                    let rhs_bb = self.context.append_basic_block(parent, "rhs");
+                    let cont_bb = self.context.append_basic_block(parent, "logicont");
+
+                    if op == "and" {
+                        self.builder
+                            .build_conditional_branch(lhs, rhs_bb, cont_bb)
+                            .unwrap();
+                    } else {
+                        self.builder
+                            .build_conditional_branch(lhs, cont_bb, rhs_bb)
+                            .unwrap();
+                    }
+
+                    // build right-hand side block
+                    self.builder.position_at_end(rhs_bb);
+
+                    let rhs = self.compile_expr(right)?;
+                    let rhs = self
+                        .builder
+                        .build_float_compare(FloatPredicate::ONE, rhs, zero_const, "rhsbool")
+                        .unwrap();
+
+                    self.builder.build_unconditional_branch(cont_bb).unwrap();
+
+                    let rhs_bb = self.builder.get_insert_block().unwrap();
+
+                    // emit merge block
+                    self.builder.position_at_end(cont_bb);
+
+                    let phi = self
+                        .builder
+                        .build_phi(self.context.bool_type(), "logictmp")
+                        .unwrap();
+
+                    phi.add_incoming(&[(&lhs, lhs_bb), (&rhs, rhs_bb)]);
+
+                    Ok(self
+                        .builder
+                        .build_unsigned_int_to_float(
+                            phi.as_basic_value().into_int_value(),
+                            self.context.f64_type(),
+                            "tmpbool",
+                        )
+                        .unwrap())
                 } else {
                     let lhs = self.compile_expr(left)?;
                     let rhs = self.compile_expr(right)?;
 
-                    match op {
-                        '+' => Ok(self.builder.build_float_add(lhs, rhs, "tmpadd").unwrap()),
-                        '-' => Ok(self.builder.build_float_sub(lhs, rhs, "tmpsub").unwrap()),
-                        '*' => Ok(self.builder.build_float_mul(lhs, rhs, "tmpmul").unwrap()),
-                        '/' => Ok(self.builder.build_float_div(lhs, rhs, "tmpdiv").unwrap()),
-                        '<' => Ok({
+                    match op.as_str() {
+                        "+" => Ok(self.builder.build_float_add(lhs, rhs, "tmpadd").unwrap()),
+                        "-" => Ok(self.builder.build_float_sub(lhs, rhs, "tmpsub").unwrap()),
+                        "*" => Ok(self.builder.build_float_mul(lhs, rhs, "tmpmul").unwrap()),
+                        "/" => Ok(self.builder.build_float_div(lhs, rhs, "tmpdiv").unwrap()),
+                        "//" => {
+                            // round the quotient towards negative infinity
+                            let quotient =
+                                self.builder.build_float_div(lhs, rhs, "tmpdiv").unwrap();
+
+                            self.build_unary_intrinsic_call("llvm.floor", quotient, "tmpfloordiv")
+                        }
+                        "%" => {
+                            if rhs.get_constant() == Some((0.0, false)) {
+                                return Err("Modulo by zero.");
+                            }
+
+                            Ok(self.builder.build_float_rem(lhs, rhs, "tmprem").unwrap())
+                        }
+                        "<" | ">" | "<=" | ">=" | "==" | "!=" => {
+                            // comparisons evaluate to 1.0 when true and 0.0 otherwise
+                            let predicate = match op.as_str() {
+                                "<" => FloatPredicate::ULT,
+                                ">" => FloatPredicate::UGT,
+                                "<=" => FloatPredicate::ULE,
+                                ">=" => FloatPredicate::UGE,
+                                "==" => FloatPredicate::OEQ,
+                                _ => FloatPredicate::UNE,
+                            };
+
                             let cmp = self
                                 .builder
-                                .build_float_compare(FloatPredicate::ULT, lhs, rhs, "tmpcmp")
+                                .build_float_compare(predicate, lhs, rhs, "tmpcmp")
                                 .unwrap();
 
-                            self.builder
+                            Ok(self
+                                .builder
                                 .build_unsigned_int_to_float(
                                     cmp,
                                     self.context.f64_type(),
                                     "tmpbool",
                                 )
-                                .unwrap()
-                        }),
-                        '>' => Ok({
-                            let cmp = self
+                                .unwrap())
+                        }
+
+                        "&" | "|" | "^" | "<<" | ">>" => {
+                            let is_shift = op == "<<" || op == ">>";
+                            let count = rhs;
+
+                            if is_shift
+                                && matches!(count.get_constant(), Some((nb, _)) if !is_shift_count(nb))
+                            {
+                                return Err("Shift count out of range.");
+                            }
+
+                            // bitwise operators work on the operands truncated to integers
+                            let lhs = self.build_float_to_i64(lhs);
+                            let rhs = self.build_float_to_i64(rhs);
+
+                            let result = match op.as_str() {
+                                "&" => self.builder.build_and(lhs, rhs, "tmpand"),
+                                "|" => self.builder.build_or(lhs, rhs, "tmpor"),
+                                "^" => self.builder.build_xor(lhs, rhs, "tmpxor"),
+                                "<<" => self.builder.build_left_shift(lhs, rhs, "tmpshl"),
+                                _ => self.builder.build_right_shift(lhs, rhs, true, "tmpshr"),
+                            }
+                            .unwrap();
+
+                            let result = self
                                 .builder
-                                .build_float_compare(FloatPredicate::ULT, rhs, lhs, "tmpcmp")
+                                .build_signed_int_to_float(
+                                    result,
+                                    self.context.f64_type(),
+                                    "tmpbits",
+                                )
                                 .unwrap();
 
-                            self.builder
-                                .build_unsigned_int_to_float(
-                                    cmp,
-                                    self.context.f64_type(),
-                                    "tmpbool",
+                            if !is_shift {
+                                return Ok(result);
+                            }
+
+                            // shifting by a negative count, or by 64 bits or more, evaluates to NaN
+                            let f64_type = self.context.f64_type();
+                            let above_min = self
+                                .builder
+                                .build_float_compare(
+                                    FloatPredicate::OGT,
+                                    count,
+                                    f64_type.const_float(-1.0),
+                                    "shiftmin",
+                                )
+                                .unwrap();
+                            let below_max = self
+                                .builder
+                                .build_float_compare(
+                                    FloatPredicate::OLT,
+                                    count,
+                                    f64_type.const_float(64.0),
+                                    "shiftmax",
+                                )
+                                .unwrap();
+                            let in_range = self
+                                .builder
+                                .build_and(above_min, below_max, "inrange")
+                                .unwrap();
+
+                            Ok(self
+                                .builder
+                                .build_select(
+                                    in_range,
+                                    result,
+                                    f64_type.const_float(f64::NAN),
+                                    "tmpshift",
                                 )
                                 .unwrap()
-                        }),
+                                .into_float_value())
+                        }
+
+                        "**" => {
+                            let pow = Intrinsic::find("llvm.pow")
+                                .and_then(|pow| {
+                                    pow.get_declaration(
+                                        self.module,
+                                        &[self.context.f64_type().into()],
+                                    )
+                                })
+                                .ok_or("Could not declare the 'llvm.pow' intrinsic.")?;
+
+                            match self
+                                .builder
+                                .build_call(pow, &[lhs.into(), rhs.into()], "tmppow")
+                                .unwrap()
+                                .try_as_basic_value()
+                                .left()
+                            {
+                                Some(value) => Ok(value.into_float_value()),
+                                None => Err("Invalid call produced."),
+                            }
+                        }
 
                         custom => {
                             let mut name = String::from("binary");
 
-                            name.push(custom);
+                            name.push_str(custom);
 
                             match self.get_function(name.as_str()) {
                                 Some(fun) => {
@@ -1035,7 +1504,186 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
                         None => Err("Invalid call produced."),
                     }
                 }
-                None => Err("Unknown function."),
+
+                // fall back to the built-in operators and functions when they are not user-defined
+                None => match (fn_name.as_str(), args.as_slice()) {
+                    ("unary-", [operand]) => {
+                        let operand = self.compile_expr(operand)?;
+
+                        Ok(self.builder.build_float_neg(operand, "tmpneg").unwrap())
+                    }
+                    ("unary+", [operand]) => self.compile_expr(operand),
+                    (FACTORIAL_FUNCTION_NAME, [operand]) => {
+                        let operand = self.compile_expr(operand)?;
+
+                        match operand.get_constant() {
+                            Some((nb, _)) if nb < 0.0 => Err("Factorial of a negative number."),
+                            Some((nb, _)) if nb.fract() != 0.0 => {
+                                Err("Factorial of a non-integer number.")
+                            }
+                            _ => self.build_factorial(operand),
+                        }
+                    }
+                    ("unarynot", [operand]) => {
+                        let operand = self.compile_expr(operand)?;
+                        let cmp = self
+                            .builder
+                            .build_float_compare(
+                                FloatPredicate::OEQ,
+                                operand,
+                                self.context.f64_type().const_float(0.0),
+                                "tmpnot",
+                            )
+                            .unwrap();
+
+                        Ok(self
+                            .builder
+                            .build_unsigned_int_to_float(cmp, self.context.f64_type(), "tmpbool")
+                            .unwrap())
+                    }
+
+                    ("abs", [operand]) => {
+                        let operand = self.compile_expr(operand)?;
+
+                        self.build_unary_intrinsic_call("llvm.fabs", operand, "tmpabs")
+                    }
+                    ("round", [operand]) => {
+                        // rounds halfway cases away from zero
+                        let operand = self.compile_expr(operand)?;
+
+                        self.build_unary_intrinsic_call("llvm.round", operand, "tmpround")
+                    }
+                    ("floor", [operand]) => {
+                        let operand = self.compile_expr(operand)?;
+
+                        self.build_unary_intrinsic_call("llvm.floor", operand, "tmpfloor")
+                    }
+                    ("ceil", [operand]) => {
+                        let operand = self.compile_expr(operand)?;
+
+                        self.build_unary_intrinsic_call("llvm.ceil", operand, "tmpceil")
+                    }
+                    ("isqrt", [operand]) => {
+                        let operand = self.compile_expr(operand)?;
+
+                        if matches!(operand.get_constant(), Some((nb, _)) if nb < 0.0) {
+                            return Err("Square root of a negative number.");
+                        }
+
+                        // a negative operand only known at runtime gives NaN
+                        let sqrt =
+                            self.build_unary_intrinsic_call("llvm.sqrt", operand, "tmpsqrt")?;
+                        let root =
+                            self.build_unary_intrinsic_call("llvm.floor", sqrt, "tmpfloor")?;
+
+                        // the rounded square root of an integer just below a square may be
+                        // one too large, as for (2^26 + 1)^2 - 1
+                        let square = self
+                            .builder
+                            .build_float_mul(root, root, "tmpsquare")
+                            .unwrap();
+                        let too_large = self
+                            .builder
+                            .build_float_compare(FloatPredicate::OGT, square, operand, "toolarge")
+                            .unwrap();
+                        let below = self
+                            .builder
+                            .build_float_sub(
+                                root,
+                                self.context.f64_type().const_float(1.0),
+                                "tmpbelow",
+                            )
+                            .unwrap();
+
+                        Ok(self
+                            .builder
+                            .build_select(too_large, below, root, "tmpisqrt")
+                            .unwrap()
+                            .into_float_value())
+                    }
+                    ("rand", [bound]) => {
+                        let bound = self.compile_expr(bound)?;
+                        let (state, random) = declare_random(self.context, self.module);
+
+                        let args = [state.as_pointer_value().into(), bound.into()];
+
+                        match self
+                            .builder
+                            .build_call(random, &args, "tmprand")
+                            .unwrap()
+                            .try_as_basic_value()
+                            .left()
+                        {
+                            Some(value) => Ok(value.into_float_value()),
+                            None => Err("Invalid call produced."),
+                        }
+                    }
+                    ("gcd", [lhs, rhs]) => {
+                        let lhs = self.compile_expr(lhs)?;
+                        let rhs = self.compile_expr(rhs)?;
+
+                        self.build_gcd(lhs, rhs)
+                    }
+                    ("wadd" | "wsub" | "wmul", [lhs, rhs]) => {
+                        // wrapping arithmetic on the operands truncated to 64-bit integers
+                        let lhs = self.compile_expr(lhs)?;
+                        let rhs = self.compile_expr(rhs)?;
+                        let lhs = self.build_float_to_i64(lhs);
+                        let rhs = self.build_float_to_i64(rhs);
+
+                        let result = match fn_name.as_str() {
+                            "wadd" => self.builder.build_int_add(lhs, rhs, "tmpwadd"),
+                            "wsub" => self.builder.build_int_sub(lhs, rhs, "tmpwsub"),
+                            _ => self.builder.build_int_mul(lhs, rhs, "tmpwmul"),
+                        }
+                        .unwrap();
+
+                        Ok(self
+                            .builder
+                            .build_signed_int_to_float(result, self.context.f64_type(), "tmpwrap")
+                            .unwrap())
+                    }
+                    ("max" | "min", [first, rest @ ..]) if !rest.is_empty() => {
+                        let predicate = if fn_name == "max" {
+                            FloatPredicate::OGT
+                        } else {
+                            FloatPredicate::OLT
+                        };
+
+                        // fold the arguments from left to right
+                        let mut lhs = self.compile_expr(first)?;
+
+                        for rhs in rest {
+                            let rhs = self.compile_expr(rhs)?;
+
+                            let cmp = self
+                                .builder
+                                .build_float_compare(predicate, lhs, rhs, "tmpcmp")
+                                .unwrap();
+
+                            lhs = self
+                                .builder
+                                .build_select(cmp, lhs, rhs, "tmpsel")
+                                .unwrap()
+                                .into_float_value();
+                        }
+
+                        Ok(lhs)
+                    }
+                    (
+                        "abs" | "round" | "floor" | "ceil" | "isqrt" | "rand" | "gcd" | "wadd"
+                        | "wsub" | "wmul" | "max" | "min",
+                        _,
+                    ) => Err("Wrong number of arguments passed to built-in function."),
+
+                    // a binary operator found where an operand was expected, as in '* 5' or '2 + * 3'
+                    (name, _) if name.strip_prefix("unary").is_some_and(is_binary_op_name) => {
+                        Err("Expected an operand before binary operator.")
+                    }
+                    (name, _) if is_unary_op_name(name) => Err("Undefined unary operator."),
+
+                    _ => Err("Unknown function."),
+                },
             },
 
             Expr::Conditional {
@@ -1160,13 +1808,283 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
         }
     }
 
+    /// Truncates the given value to a 64-bit integer, saturating values out of range and
+    /// mapping NaN to 0 as `llvm.fptosi.sat` and Rust's `as i64` do. A plain `fptosi` gives
+    /// poison for those, and unlike a call to the intrinsic, constants are still folded.
+    fn build_float_to_i64(&self, value: FloatValue<'ctx>) -> IntValue<'ctx> {
+        let f64_type = self.context.f64_type();
+        let i64_type = self.context.i64_type();
+        let bound = 2f64.powi(63);
+
+        let truncated = self
+            .builder
+            .build_float_to_signed_int(value, i64_type, "tmpint")
+            .unwrap();
+
+        // only keep the truncated value when it is defined, that is within [-2^63, 2^63)
+        let above_min = self
+            .builder
+            .build_float_compare(
+                FloatPredicate::OGE,
+                value,
+                f64_type.const_float(-bound),
+                "intmin",
+            )
+            .unwrap();
+        let below_max = self
+            .builder
+            .build_float_compare(
+                FloatPredicate::OLT,
+                value,
+                f64_type.const_float(bound),
+                "intmax",
+            )
+            .unwrap();
+        let in_range = self
+            .builder
+            .build_and(above_min, below_max, "inrange")
+            .unwrap();
+
+        let is_positive = self
+            .builder
+            .build_float_compare(
+                FloatPredicate::OGT,
+                value,
+                f64_type.const_float(0.0),
+                "ispos",
+            )
+            .unwrap();
+        let is_negative = self
+            .builder
+            .build_float_compare(
+                FloatPredicate::OLT,
+                value,
+                f64_type.const_float(0.0),
+                "isneg",
+            )
+            .unwrap();
+
+        let saturated = self
+            .builder
+            .build_select(
+                is_negative,
+                i64_type.const_int(i64::MIN as u64, false),
+                i64_type.const_zero(),
+                "tmpsat",
+            )
+            .unwrap()
+            .into_int_value();
+        let saturated = self
+            .builder
+            .build_select(
+                is_positive,
+                i64_type.const_int(i64::MAX as u64, false),
+                saturated,
+                "tmpsat",
+            )
+            .unwrap()
+            .into_int_value();
+
+        self.builder
+            .build_select(in_range, truncated, saturated, "tmpint")
+            .unwrap()
+            .into_int_value()
+    }
+
+    /// Builds a call to the LLVM intrinsic of the given name, taking and returning an `f64`.
+    fn build_unary_intrinsic_call(
+        &self,
+        name: &str,
+        operand: FloatValue<'ctx>,
+        result_name: &str,
+    ) -> Result<FloatValue<'ctx>, &'static str> {
+        let intrinsic = Intrinsic::find(name)
+            .and_then(|intrinsic| {
+                intrinsic.get_declaration(self.module, &[self.context.f64_type().into()])
+            })
+            .ok_or("Could not declare LLVM intrinsic.")?;
+
+        match self
+            .builder
+            .build_call(intrinsic, &[operand.into()], result_name)
+            .unwrap()
+            .try_as_basic_value()
+            .left()
+        {
+            Some(value) => Ok(value.into_float_value()),
+            None => Err("Invalid call produced."),
+        }
+    }
+
+    /// Builds a loop computing the greatest common divisor of the given values
+    /// with Euclid's algorithm.
+    fn build_gcd(
+        &self,
+        lhs: FloatValue<'ctx>,
+        rhs: FloatValue<'ctx>,
+    ) -> Result<FloatValue<'ctx>, &'static str> {
+        let parent = self.fn_value();
+        let f64_type = self.context.f64_type();
+
+        let lhs = self.build_unary_intrinsic_call("llvm.fabs", lhs, "tmpabs")?;
+        let rhs = self.build_unary_intrinsic_call("llvm.fabs", rhs, "tmpabs")?;
+
+        let entry_bb = self.builder.get_insert_block().unwrap();
+        let loop_bb = self.context.append_basic_block(parent, "gcdloop");
+        let body_bb = self.context.append_basic_block(parent, "gcdbody");
+        let after_bb = self.context.append_basic_block(parent, "aftergcd");
+
+        self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+        // replace (a, b) by (b, a % b) until b is 0, or NaN so that the loop always ends
+        self.builder.position_at_end(loop_bb);
+
+        let a = self.builder.build_phi(f64_type, "a").unwrap();
+        let b = self.builder.build_phi(f64_type, "b").unwrap();
+        let a_val = a.as_basic_value().into_float_value();
+        let b_val = b.as_basic_value().into_float_value();
+
+        let loop_cond = self
+            .builder
+            .build_float_compare(
+                FloatPredicate::ONE,
+                b_val,
+                f64_type.const_float(0.0),
+                "loopcond",
+            )
+            .unwrap();
+
+        self.builder
+            .build_conditional_branch(loop_cond, body_bb, after_bb)
+            .unwrap();
+
+        self.builder.position_at_end(body_bb);
+
+        let rem = self
+            .builder
+            .build_float_rem(a_val, b_val, "tmprem")
+            .unwrap();
+
+        self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+        a.add_incoming(&[(&lhs, entry_bb), (&b_val, body_bb)]);
+        b.add_incoming(&[(&rhs, entry_bb), (&rem, body_bb)]);
+
+        self.builder.position_at_end(after_bb);
+
+        Ok(a_val)
+    }
+
+    /// Builds a loop computing the factorial of the given value, which evaluates to
+    /// NaN when it is negative or not an integer.
+    fn build_factorial(&self, n: FloatValue<'ctx>) -> Result<FloatValue<'ctx>, &'static str> {
+        let parent = self.fn_value();
+        let f64_type = self.context.f64_type();
+
+        // check that the operand is a non-negative integer
+        let floor_n = self.build_unary_intrinsic_call("llvm.floor", n, "tmpfloor")?;
+
+        let is_int = self
+            .builder
+            .build_float_compare(FloatPredicate::OEQ, floor_n, n, "isint")
+            .unwrap();
+        let is_positive = self
+            .builder
+            .build_float_compare(FloatPredicate::OGE, n, f64_type.const_float(0.0), "ispos")
+            .unwrap();
+        let is_valid = self
+            .builder
+            .build_and(is_int, is_positive, "isvalid")
+            .unwrap();
+
+        // large operands skip the loop, their factorial being an infinity
+        let is_small = self
+            .builder
+            .build_float_compare(
+                FloatPredicate::OLE,
+                n,
+                f64_type.const_float(MAX_FACTORIAL_OPERAND),
+                "issmall",
+            )
+            .unwrap();
+        let in_loop = self
+            .builder
+            .build_and(is_valid, is_small, "inloop")
+            .unwrap();
+        let skipped = self
+            .builder
+            .build_select(
+                is_valid,
+                f64_type.const_float(f64::INFINITY),
+                f64_type.const_float(f64::NAN),
+                "skipped",
+            )
+            .unwrap();
+
+        let entry_bb = self.builder.get_insert_block().unwrap();
+        let loop_bb = self.context.append_basic_block(parent, "factloop");
+        let body_bb = self.context.append_basic_block(parent, "factbody");
+        let after_bb = self.context.append_basic_block(parent, "afterfact");
+
+        self.builder
+            .build_conditional_branch(in_loop, loop_bb, after_bb)
+            .unwrap();
+
+        // multiply the product by every number from the operand down to 2
+        self.builder.position_at_end(loop_bb);
+
+        let counter = self.builder.build_phi(f64_type, "counter").unwrap();
+        let product = self.builder.build_phi(f64_type, "product").unwrap();
+        let counter_val = counter.as_basic_value().into_float_value();
+        let product_val = product.as_basic_value().into_float_value();
+
+        let loop_cond = self
+            .builder
+            .build_float_compare(
+                FloatPredicate::OGT,
+                counter_val,
+                f64_type.const_float(1.0),
+                "loopcond",
+            )
+            .unwrap();
+
+        self.builder
+            .build_conditional_branch(loop_cond, body_bb, after_bb)
+            .unwrap();
+
+        self.builder.position_at_end(body_bb);
+
+        let next_product = self
+            .builder
+            .build_float_mul(product_val, counter_val, "nextproduct")
+            .unwrap();
+        let next_counter = self
+            .builder
+            .build_float_sub(counter_val, f64_type.const_float(1.0), "nextcounter")
+            .unwrap();
+
+        self.builder.build_unconditional_branch(loop_bb).unwrap();
+
+        counter.add_incoming(&[(&n, entry_bb), (&next_counter, body_bb)]);
+        product.add_incoming(&[
+            (&f64_type.const_float(1.0), entry_bb),
+            (&next_product, body_bb),
+        ]);
+
+        // emit merge block
+        self.builder.position_at_end(after_bb);
+
+        let phi = self.builder.build_phi(f64_type, "facttmp").unwrap();
+
+        phi.add_incoming(&[(&skipped, entry_bb), (&product_val, loop_bb)]);
+
+        Ok(phi.as_basic_value().into_float_value())
+    }
+
     /// Compiles the specified `Prototype` into an extern LLVM `FunctionValue`.
     fn compile_prototype(&self, proto: &Prototype) -> Result<FunctionValue<'ctx>, &'static str> {
         let ret_type = self.context.f64_type();
-        let args_types = std::iter::repeat(ret_type)
-            .take(proto.args.len())
-            .map(|f| f.into())
-            .collect::<Vec<BasicMetadataTypeEnum>>();
+        let args_types: Vec<BasicMetadataTypeEnum> = vec![ret_type.into(); proto.args.len()];
         let args_types = args_types.as_slice();
 
         let fn_type = self.context.f64_type().fn_type(args_types, false);
@@ -1228,17 +2146,20 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
     }
 
     /// Compiles the specified `Function` in the given `Context` and using the specified `Builder` and `Module`.
+    /// Variables that are not local to the function are looked up in `globals`, whose storage is owned by the caller.
     pub fn compile(
         context: &'ctx Context,
         builder: &'a Builder<'ctx>,
         module: &'a Module<'ctx>,
         function: &Function,
+        globals: &HashMap<String, Box<f64>>,
     ) -> Result<FunctionValue<'ctx>, &'static str> {
         let mut compiler = Compiler {
             context,
             builder,
             module,
             function,
+            globals,
             fn_value_opt: None,
             variables: HashMap::new(),
         };
@@ -1246,3 +2167,388 @@ impl<'a, 'ctx> Compiler<'a, 'ctx> {
         compiler.compile_fn()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses the given input with the precedences of the built-in binary operators.
+    fn parse(input: &str) -> Result<Function, ParseError> {
+        let mut prec: HashMap<String, i32> = BINARY_OPERATORS
+            .iter()
+            .map(|&(op, op_prec)| (op.to_string(), op_prec))
+            .collect();
+
+        Parser::new(input.to_string(), &mut prec).parse()
+    }
+
+    fn parse_body(input: &str) -> Expr {
+        parse(input).unwrap().body.unwrap()
+    }
+
+    fn binary(op: &str, left: Expr, right: Expr) -> Expr {
+        Expr::Binary {
+            op: op.to_string(),
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    #[test]
+    fn parse_binary_precedence() {
+        let mut prec = HashMap::new();
+
+        prec.insert("+".to_string(), 20);
+        prec.insert("*".to_string(), 40);
+        prec.insert("**".to_string(), 60);
+
+        let fun = Parser::new("1 + 2 * 3 ** 2 ** 0".to_string(), &mut prec)
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            fun.body.unwrap(),
+            binary(
+                "+",
+                Expr::Number(1.0),
+                binary(
+                    "*",
+                    Expr::Number(2.0),
+                    binary(
+                        "**",
+                        Expr::Number(3.0),
+                        binary("**", Expr::Number(2.0), Expr::Number(0.0))
+                    )
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn parse_chained_comparisons() {
+        let var = |name: &str| Expr::Variable(name.to_string());
+        let var_in = |name: &str, init: Expr, body: Expr| Expr::VarIn {
+            variables: vec![(name.to_string(), Some(init))],
+            body: Box::new(body),
+        };
+
+        assert_eq!(parse_body("a < b"), binary("<", var("a"), var("b")));
+        assert_eq!(
+            parse_body("(a < b) < c"),
+            binary("<", binary("<", var("a"), var("b")), var("c"))
+        );
+        assert_eq!(
+            parse_body("a < b <= c + d"),
+            var_in(
+                "chained.1",
+                var("a"),
+                var_in(
+                    "chained.2",
+                    var("b"),
+                    binary(
+                        "and",
+                        binary("<", var("chained.1"), var("chained.2")),
+                        binary("<=", var("chained.2"), binary("+", var("c"), var("d")))
+                    )
+                )
+            )
+        );
+    }
+
+    #[test]
+    fn parse_unary_and_call() {
+        assert_eq!(
+            parse_body("-f(x, 2)"),
+            Expr::Call {
+                fn_name: "unary-".to_string(),
+                args: vec![Expr::Call {
+                    fn_name: "f".to_string(),
+                    args: vec![Expr::Variable("x".to_string()), Expr::Number(2.0)],
+                }],
+            }
+        );
+        assert_eq!(
+            parse_body("-f()"),
+            Expr::Call {
+                fn_name: "unary-".to_string(),
+                args: vec![Expr::Call {
+                    fn_name: "f".to_string(),
+                    args: vec![],
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn parse_call_without_arguments() {
+        let call = || Expr::Call {
+            fn_name: "roll".to_string(),
+            args: vec![],
+        };
+
+        assert_eq!(
+            parse_body("roll() + 1"),
+            binary("+", call(), Expr::Number(1.0))
+        );
+        assert_eq!(
+            parse("def twice() roll() * 2").unwrap().body.unwrap(),
+            binary("*", call(), Expr::Number(2.0))
+        );
+    }
+
+    #[test]
+    fn parse_factorial() {
+        let factorial = |operand: Expr| Expr::Call {
+            fn_name: "postfix!".to_string(),
+            args: vec![operand],
+        };
+
+        assert_eq!(
+            parse_body("-3! + 1"),
+            binary(
+                "+",
+                Expr::Call {
+                    fn_name: "unary-".to_string(),
+                    args: vec![factorial(Expr::Number(3.0))],
+                },
+                Expr::Number(1.0)
+            )
+        );
+        assert_eq!(
+            parse_body("(2 + 1)!!"),
+            factorial(factorial(binary("+", Expr::Number(2.0), Expr::Number(1.0))))
+        );
+    }
+
+    #[test]
+    fn parse_percent() {
+        let parse_percent = |input: &str| {
+            Parser::new(input.to_string(), &mut HashMap::new())
+                .with_percent()
+                .parse()
+                .unwrap()
+                .body
+                .unwrap()
+        };
+        let percent = |operand: Expr| binary("/", operand, Expr::Number(100.0));
+
+        assert_eq!(parse_percent("50%"), percent(Expr::Number(50.0)));
+        assert_eq!(
+            parse_percent("(3!)%%"),
+            percent(percent(Expr::Call {
+                fn_name: "postfix!".to_string(),
+                args: vec![Expr::Number(3.0)],
+            }))
+        );
+        assert!(parse("50%").is_err());
+    }
+
+    #[test]
+    fn parse_postfix_conditional() {
+        let conditional = |cond: Expr, consequence: Expr, alternative: Expr| Expr::Conditional {
+            cond: Box::new(cond),
+            consequence: Box::new(consequence),
+            alternative: Box::new(alternative),
+        };
+
+        assert_eq!(
+            parse_body("1 if a else 2 if b else 3"),
+            conditional(
+                Expr::Variable("a".to_string()),
+                Expr::Number(1.0),
+                conditional(
+                    Expr::Variable("b".to_string()),
+                    Expr::Number(2.0),
+                    Expr::Number(3.0)
+                )
+            )
+        );
+        assert_eq!(
+            parse("1 if a").unwrap_err().error,
+            "Expected 'else' keyword."
+        );
+    }
+
+    #[test]
+    fn parse_skips_comments() {
+        assert_eq!(
+            parse_body("x # a comment (\n"),
+            Expr::Variable("x".to_string())
+        );
+        assert_eq!(
+            parse_body("(x # a comment\n)"),
+            Expr::Variable("x".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_definition() {
+        assert_eq!(
+            parse("def f(a, b) a").unwrap(),
+            Function {
+                prototype: Prototype {
+                    name: "f".to_string(),
+                    args: vec!["a".to_string(), "b".to_string()],
+                    is_op: false,
+                    prec: 0,
+                },
+                body: Some(Expr::Variable("a".to_string())),
+                is_anon: false,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_nesting_limit() {
+        let nested = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+
+        assert!(parse(&nested(150)).is_ok());
+
+        let err = parse(&format!("{}1{}", "(-".repeat(100_000), ")".repeat(100_000))).unwrap_err();
+
+        assert_eq!(err.error, "Maximum recursion depth exceeded.");
+
+        let err = parse(&format!("{}1", "-".repeat(100_000))).unwrap_err();
+
+        assert_eq!(err.error, "Maximum recursion depth exceeded.");
+    }
+
+    #[test]
+    fn parse_redundant_parentheses() {
+        let nested = |depth| format!("{}5{}", "(".repeat(depth), ")".repeat(depth));
+
+        assert_eq!(parse_body(&nested(500)), Expr::Number(5.0));
+        assert_eq!(parse_body(&nested(100_000)), Expr::Number(5.0));
+        assert_eq!(
+            parse_body("((-(x)!)) if ((1)) else (((f(1))!))"),
+            parse_body("-x! if 1 else f(1)!")
+        );
+
+        for input in ["((1)", "((1) 2)", "((()))", "(("] {
+            assert!(parse(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_right_associative_nesting_limit() {
+        let mut prec = HashMap::new();
+
+        prec.insert("**".to_string(), 60);
+
+        let input = format!("2{}", " ** 2".repeat(100_000));
+        let err = Parser::new(input, &mut prec).parse().unwrap_err();
+
+        assert_eq!(err.error, "Maximum recursion depth exceeded.");
+    }
+
+    #[test]
+    fn parse_unexpected_end() {
+        for input in ["def", "extern", "def f(a,", "var a = 1"] {
+            assert_eq!(parse(input).unwrap_err().error, "Unexpected end of file.");
+        }
+    }
+
+    #[test]
+    fn pretty_print() {
+        let mut prec = HashMap::new();
+
+        for &(op, op_prec) in BINARY_OPERATORS {
+            prec.insert(op.to_string(), op_prec);
+        }
+
+        let mut parse_body = |input: &str| {
+            Parser::new(input.to_string(), &mut prec)
+                .parse()
+                .unwrap()
+                .body
+                .unwrap()
+        };
+
+        for (input, expected) in [
+            ("2 + 3 * 4", "(2 + (3 * 4))"),
+            ("2 - 3 - 4", "((2 - 3) - 4)"),
+            ("2 ** 3 ** 2", "(2 ** (3 ** 2))"),
+            ("-x! + not 1.5", "((-(x!)) + (not 1.5))"),
+            ("f(1, g()) // 2", "(f(1, g()) // 2)"),
+            ("x = 1 or 0 and y", "(x = (1 or (0 and y)))"),
+            ("a if a > 0 else -a", "(if (a > 0) then a else (-a))"),
+            (
+                "for i = 1, i < 10, 2 in var s = i, t in s * t",
+                "(for i = 1, (i < 10), 2 in (var s = i, t in (s * t)))",
+            ),
+        ] {
+            let expr = parse_body(input);
+            let printed = pretty(&expr);
+
+            assert_eq!(printed, expected);
+            assert_eq!(parse_body(&printed), expr, "{}", printed);
+        }
+    }
+
+    #[test]
+    fn parse_missing_right_operand() {
+        for input in ["5 /", "5 +", "5 *", "1 + 2 *  "] {
+            let err = parse(input).unwrap_err();
+
+            assert_eq!(err.error, "Expected an operand after binary operator.");
+            assert_eq!(err.index, input.trim_end().len());
+        }
+    }
+
+    #[test]
+    fn parse_error_column() {
+        let err = parse("12 + )").unwrap_err();
+
+        assert_eq!(err.index, 5);
+        assert_eq!(err.to_string(), "Unknown expression. (at column 6)");
+    }
+
+    #[test]
+    fn parse_unmatched_paren() {
+        let err = parse("2 + 3)").unwrap_err();
+
+        assert_eq!(err.index, 5);
+        assert_eq!(err.to_string(), "Unmatched ')'. (at column 6)");
+
+        let err = parse(")").unwrap_err();
+
+        assert_eq!(err.index, 0);
+        assert_eq!(err.error, "Unmatched ')'.");
+        assert_eq!(parse("(1 + 2))").unwrap_err().index, 7);
+    }
+
+    #[test]
+    fn parse_empty_parens() {
+        for (input, index) in [("()", 1), ("( )", 2), ("(())", 2), ("1 + ()", 5)] {
+            let err = parse(input).unwrap_err();
+
+            assert_eq!(err.error, "Empty parentheses.", "{}", input);
+            assert_eq!(err.index, index, "{}", input);
+        }
+
+        assert_eq!(parse_body("(1)"), Expr::Number(1.0));
+        assert_eq!(parse_body("((1))"), Expr::Number(1.0));
+    }
+
+    #[test]
+    fn parse_error_column_at_end() {
+        let err = parse("(1 + 2\n").unwrap_err();
+
+        assert_eq!(err.index, 6);
+    }
+
+    #[test]
+    fn parse_lex_error_column() {
+        let err = parse("1 + 1a").unwrap_err();
+
+        assert_eq!(err.index, 4);
+        assert_eq!(err.span, 4..6);
+    }
+
+    #[test]
+    fn parse_error_span() {
+        assert_eq!(parse("12 + )").unwrap_err().span, 5..6);
+        assert_eq!(parse("ａ ＋ ｄｅｆ").unwrap_err().span, 8..17);
+        assert_eq!(parse("(1 + 2\n").unwrap_err().span, 6..6);
+    }
+}
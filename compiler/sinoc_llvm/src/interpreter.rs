@@ -0,0 +1,674 @@
+//! A tree-walking interpreter evaluating the AST directly, used instead of the JIT
+//! on platforms where it is unavailable.
+//!
+//! The interpreter follows the semantics of the code generated by the `Compiler`,
+//! and reports the same errors. However, whereas the compiler rejects an input as
+//! a whole before running it, the interpreter only reports an error once the
+//! evaluation reaches it.
+
+use std::collections::HashMap;
+
+use crate::implementation_typed_pointers::*;
+use crate::session::{bool_to_f64, random_below, random_state, EvalError, LAST_RESULT};
+
+/// Evaluates a top-level expression without compiling it, looking up the variables
+/// it does not define in `env`. Assignments to global variables are not kept.
+pub fn interpret(expr: &Expr, env: &HashMap<String, f64>) -> Result<f64, EvalError> {
+    let mut globals = env
+        .iter()
+        .map(|(name, &value)| (name.clone(), Box::new(value)))
+        .collect();
+    let mut random_state = random_state(0);
+
+    Interpreter::new(&[], &mut globals, &mut random_state).eval(expr)
+}
+
+/// Returns a value indicating whether or not the given value is true as a condition,
+/// that is neither 0.0 nor NaN.
+fn is_true(value: f64) -> bool {
+    !value.is_nan() && value != 0.0
+}
+
+/// Calls the function of the C math library of the given name, which an `extern`
+/// declaration links to when running on the JIT.
+fn call_extern(name: &str, args: &[f64]) -> Option<f64> {
+    let value = match (name, args) {
+        ("sin", &[x]) => x.sin(),
+        ("cos", &[x]) => x.cos(),
+        ("tan", &[x]) => x.tan(),
+        ("asin", &[x]) => x.asin(),
+        ("acos", &[x]) => x.acos(),
+        ("atan", &[x]) => x.atan(),
+        ("sinh", &[x]) => x.sinh(),
+        ("cosh", &[x]) => x.cosh(),
+        ("tanh", &[x]) => x.tanh(),
+        ("exp", &[x]) => x.exp(),
+        ("log", &[x]) => x.ln(),
+        ("log2", &[x]) => x.log2(),
+        ("log10", &[x]) => x.log10(),
+        ("sqrt", &[x]) => x.sqrt(),
+        ("cbrt", &[x]) => x.cbrt(),
+        ("fabs", &[x]) => x.abs(),
+        ("floor", &[x]) => x.floor(),
+        ("ceil", &[x]) => x.ceil(),
+        ("round", &[x]) => x.round(),
+        ("trunc", &[x]) => x.trunc(),
+        ("pow", &[x, y]) => x.powf(y),
+        ("atan2", &[y, x]) => y.atan2(x),
+        ("hypot", &[x, y]) => x.hypot(y),
+        ("fmod", &[x, y]) => x % y,
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Computes the factorial of the given value as the compiled code does, which is
+/// NaN when it is negative or not an integer.
+fn factorial(n: f64) -> f64 {
+    if n.floor() != n || n < 0.0 {
+        return f64::NAN;
+    }
+
+    if n > MAX_FACTORIAL_OPERAND {
+        return f64::INFINITY;
+    }
+
+    let mut product = 1.0;
+    let mut counter = n;
+
+    while counter > 1.0 {
+        product *= counter;
+        counter -= 1.0;
+    }
+
+    product
+}
+
+/// Applies the given built-in binary operator as the compiled code does, or returns `None`
+/// if it is not one the compiler folds into a constant when both operands are constant.
+fn fold_binary_op(op: &str, lhs: f64, rhs: f64) -> Option<f64> {
+    // the relational operators compile to unordered comparisons
+    let unordered = lhs.is_nan() || rhs.is_nan();
+
+    let value = match op {
+        "+" => lhs + rhs,
+        "-" => lhs - rhs,
+        "*" => lhs * rhs,
+        "/" => lhs / rhs,
+        "%" => lhs % rhs,
+
+        "<" => bool_to_f64(lhs < rhs || unordered),
+        ">" => bool_to_f64(lhs > rhs || unordered),
+        "<=" => bool_to_f64(lhs <= rhs || unordered),
+        ">=" => bool_to_f64(lhs >= rhs || unordered),
+        "==" => bool_to_f64(lhs == rhs),
+        "!=" => bool_to_f64(lhs != rhs),
+
+        "&" | "|" | "^" | "<<" | ">>" => {
+            // shifting by a negative count, or by 64 bits or more, evaluates to NaN
+            if (op == "<<" || op == ">>") && !is_shift_count(rhs) {
+                return Some(f64::NAN);
+            }
+
+            // bitwise operators work on the operands truncated to integers
+            let (lhs, rhs) = (lhs as i64, rhs as i64);
+
+            let result = match op {
+                "&" => lhs & rhs,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "<<" => lhs << rhs,
+                _ => lhs >> rhs,
+            };
+
+            result as f64
+        }
+
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+/// Computes the integer square root of the given value as the compiled code does, which is
+/// NaN when it is negative.
+fn isqrt(n: f64) -> f64 {
+    let root = n.sqrt().floor();
+
+    // the rounded square root of an integer just below a square may be one too large
+    if root * root > n {
+        root - 1.0
+    } else {
+        root
+    }
+}
+
+/// Computes the greatest common divisor of the given values with Euclid's algorithm,
+/// as the compiled code does.
+fn gcd(lhs: f64, rhs: f64) -> f64 {
+    let (mut a, mut b) = (lhs.abs(), rhs.abs());
+
+    while is_true(b) {
+        (a, b) = (b, a % b);
+    }
+
+    a
+}
+
+/// Defines the `Expr` interpreter, evaluating expressions with the functions
+/// and global variables of a session.
+pub(crate) struct Interpreter<'a> {
+    declarations: &'a [Function],
+    globals: &'a mut HashMap<String, Box<f64>>,
+    random_state: &'a mut u64,
+
+    variables: HashMap<String, f64>,
+    is_anon: bool,
+    steps: Option<Vec<String>>,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Creates an interpreter evaluating top-level expressions, which may call the
+    /// given functions and define global variables in `globals`.
+    pub(crate) fn new(
+        declarations: &'a [Function],
+        globals: &'a mut HashMap<String, Box<f64>>,
+        random_state: &'a mut u64,
+    ) -> Self {
+        Interpreter {
+            declarations,
+            globals,
+            random_state,
+            variables: HashMap::new(),
+            is_anon: true,
+            steps: None,
+        }
+    }
+
+    /// Records every binary operation evaluated from now on, along with its operands
+    /// and its result, as in '3 * 4 = 12'.
+    pub(crate) fn with_trace(mut self) -> Self {
+        self.steps = Some(Vec::new());
+        self
+    }
+
+    /// Returns the binary operations recorded since tracing was enabled, in the order
+    /// they were evaluated.
+    pub(crate) fn into_steps(self) -> Vec<String> {
+        self.steps.unwrap_or_default()
+    }
+
+    /// Gets a defined function given its name, the latest definition taking precedence.
+    fn get_function(&self, name: &str) -> Option<&'a Function> {
+        self.declarations
+            .iter()
+            .rev()
+            .find(|fun| fun.prototype.name == name)
+    }
+
+    /// Gets a global variable given its name. Unknown variables are only created
+    /// when `define` is set.
+    fn get_global(&mut self, name: &str, define: bool) -> Option<&mut f64> {
+        if self.get_function(name).is_some() || !(define || self.globals.contains_key(name)) {
+            return None;
+        }
+
+        Some(
+            self.globals
+                .entry(name.to_string())
+                .or_insert_with(|| Box::new(0.0))
+                .as_mut(),
+        )
+    }
+
+    /// Returns the value of the given expression if the compiler would fold it into a constant,
+    /// which lets the interpreter report the same errors about constant operands.
+    fn constant(&self, expr: &Expr) -> Option<f64> {
+        match *expr {
+            Expr::Number(nb) => Some(nb),
+
+            // the compiler loads local variables even if they shadow a constant
+            Expr::Variable(ref name) if !self.variables.contains_key(name.as_str()) => {
+                builtin_constant(name)
+            }
+
+            Expr::Call {
+                ref fn_name,
+                ref args,
+            } if self.get_function(fn_name).is_none() => {
+                match (fn_name.as_str(), args.as_slice()) {
+                    ("unary-", [operand]) => Some(-self.constant(operand)?),
+                    ("unary+", [operand]) => self.constant(operand),
+                    ("unarynot", [operand]) => Some(bool_to_f64(self.constant(operand)? == 0.0)),
+                    _ => None,
+                }
+            }
+
+            Expr::Binary {
+                ref op,
+                ref left,
+                ref right,
+            } => {
+                let lhs = self.constant(left)?;
+                let rhs = self.constant(right)?;
+
+                fold_binary_op(op, lhs, rhs)
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Calls the given function with the given arguments, in a scope of its own.
+    fn call(&mut self, fun: &Function, args: Vec<f64>) -> Result<f64, EvalError> {
+        if args.len() != fun.prototype.args.len() {
+            return Err(EvalError::Compile("Invalid call produced."));
+        }
+
+        let body = match fun.body {
+            Some(ref body) => body,
+            None => {
+                return call_extern(&fun.prototype.name, &args).ok_or_else(|| {
+                    EvalError::Execution(format!(
+                        "Could not resolve external function '{}'.",
+                        fun.prototype.name
+                    ))
+                })
+            }
+        };
+
+        let variables = fun.prototype.args.iter().cloned().zip(args).collect();
+        let variables = std::mem::replace(&mut self.variables, variables);
+        let is_anon = std::mem::replace(&mut self.is_anon, false);
+
+        let result = self.eval(body);
+
+        self.variables = variables;
+        self.is_anon = is_anon;
+
+        result
+    }
+
+    /// Runs a `for` loop whose variable is bound. As in the compiled code, the body runs
+    /// at least once, and the end condition is evaluated after the step, before the
+    /// variable is incremented.
+    fn eval_loop(
+        &mut self,
+        var_name: &str,
+        end: &Expr,
+        step: Option<&Expr>,
+        body: &Expr,
+    ) -> Result<(), EvalError> {
+        loop {
+            self.eval(body)?;
+
+            let step = match step {
+                Some(step) => self.eval(step)?,
+                None => 1.0,
+            };
+            let end_cond = self.eval(end)?;
+
+            *self.variables.get_mut(var_name).unwrap() += step;
+
+            if !is_true(end_cond) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Applies the given binary operator to its evaluated operands; `right` is the
+    /// expression of the right-hand operand, whose constness matters to some errors.
+    fn eval_binary_op(
+        &mut self,
+        op: &str,
+        right: &Expr,
+        lhs: f64,
+        rhs: f64,
+    ) -> Result<f64, EvalError> {
+        match op {
+            "//" => Ok((lhs / rhs).floor()),
+            "**" => Ok(lhs.powf(rhs)),
+
+            "%" if self.constant(right) == Some(0.0) => Err(EvalError::Compile("Modulo by zero.")),
+            "<<" | ">>" if matches!(self.constant(right), Some(nb) if !is_shift_count(nb)) => {
+                Err(EvalError::Compile("Shift count out of range."))
+            }
+
+            _ => match fold_binary_op(op, lhs, rhs) {
+                Some(value) => Ok(value),
+                None => match self.get_function(&format!("binary{}", op)) {
+                    Some(fun) => self.call(fun, vec![lhs, rhs]),
+                    None => Err(EvalError::Compile("Undefined binary operator.")),
+                },
+            },
+        }
+    }
+
+    /// Evaluates the specified `Expr`.
+    pub(crate) fn eval(&mut self, expr: &Expr) -> Result<f64, EvalError> {
+        match *expr {
+            Expr::Number(nb) => Ok(nb),
+
+            Expr::Variable(ref name) => match self.variables.get(name.as_str()) {
+                Some(&value) => Ok(value),
+                None => builtin_constant(name)
+                    .or_else(|| self.get_global(name, false).map(|value| *value))
+                    .ok_or(EvalError::Compile("Could not find a matching variable.")),
+            },
+
+            Expr::VarIn {
+                ref variables,
+                ref body,
+            } => {
+                let mut old_bindings = Vec::new();
+
+                for (var_name, initializer) in variables {
+                    let initial_val = match *initializer {
+                        Some(ref init) => self.eval(init)?,
+                        None => 0.0,
+                    };
+
+                    let old_binding = self.variables.insert(var_name.clone(), initial_val);
+
+                    old_bindings.push((var_name, old_binding));
+                }
+
+                let body = self.eval(body);
+
+                for (var_name, binding) in old_bindings.into_iter().rev() {
+                    match binding {
+                        Some(value) => self.variables.insert(var_name.clone(), value),
+                        None => self.variables.remove(var_name),
+                    };
+                }
+
+                body
+            }
+
+            Expr::Binary {
+                ref op,
+                ref left,
+                ref right,
+            } => {
+                if op == "=" {
+                    let var_name = match **left {
+                        Expr::Variable(ref var_name) => var_name,
+                        _ => {
+                            return Err(EvalError::Compile(
+                                "Expected variable as left-hand operator of assignment.",
+                            ));
+                        }
+                    };
+
+                    let value = self.eval(right)?;
+
+                    if let Some(var) = self.variables.get_mut(var_name.as_str()) {
+                        *var = value;
+
+                        return Ok(value);
+                    }
+
+                    if var_name == LAST_RESULT {
+                        return Ok(value);
+                    }
+
+                    if builtin_constant(var_name).is_some() {
+                        return Err(EvalError::Compile("Cannot assign to a built-in constant."));
+                    }
+
+                    // top-level assignments define global variables
+                    let define = self.is_anon;
+
+                    *self
+                        .get_global(var_name, define)
+                        .ok_or(EvalError::Compile("Undefined variable."))? = value;
+
+                    return Ok(value);
+                }
+
+                if op == "and" || op == "or" {
+                    // short-circuit: the right-hand side is only evaluated when the left-hand
+                    // side does not decide the result
+                    let lhs = is_true(self.eval(left)?);
+
+                    if lhs == (op == "or") {
+                        return Ok(bool_to_f64(lhs));
+                    }
+
+                    return Ok(bool_to_f64(is_true(self.eval(right)?)));
+                }
+
+                let lhs = self.eval(left)?;
+                let rhs = self.eval(right)?;
+                let result = self.eval_binary_op(op, right, lhs, rhs)?;
+
+                if let Some(ref mut steps) = self.steps {
+                    steps.push(format!("{} {} {} = {}", lhs, op, rhs, result));
+                }
+
+                Ok(result)
+            }
+
+            Expr::Call {
+                ref fn_name,
+                ref args,
+            } => {
+                if let Some(fun) = self.get_function(fn_name) {
+                    let mut values = Vec::with_capacity(args.len());
+
+                    for arg in args {
+                        values.push(self.eval(arg)?);
+                    }
+
+                    return self.call(fun, values);
+                }
+
+                // fall back to the built-in operators and functions when they are not user-defined
+                match (fn_name.as_str(), args.as_slice()) {
+                    ("unary-", [operand]) => Ok(-self.eval(operand)?),
+                    ("unary+", [operand]) => self.eval(operand),
+                    (FACTORIAL_FUNCTION_NAME, [operand]) => {
+                        let value = self.eval(operand)?;
+
+                        match self.constant(operand) {
+                            Some(nb) if nb < 0.0 => {
+                                Err(EvalError::Compile("Factorial of a negative number."))
+                            }
+                            Some(nb) if nb.fract() != 0.0 => {
+                                Err(EvalError::Compile("Factorial of a non-integer number."))
+                            }
+                            _ => Ok(factorial(value)),
+                        }
+                    }
+                    ("unarynot", [operand]) => Ok(bool_to_f64(self.eval(operand)? == 0.0)),
+
+                    ("abs", [operand]) => Ok(self.eval(operand)?.abs()),
+                    ("round", [operand]) => Ok(self.eval(operand)?.round()),
+                    ("floor", [operand]) => Ok(self.eval(operand)?.floor()),
+                    ("ceil", [operand]) => Ok(self.eval(operand)?.ceil()),
+                    ("isqrt", [operand]) => {
+                        let value = self.eval(operand)?;
+
+                        if matches!(self.constant(operand), Some(nb) if nb < 0.0) {
+                            return Err(EvalError::Compile("Square root of a negative number."));
+                        }
+
+                        Ok(isqrt(value))
+                    }
+                    ("rand", [bound]) => {
+                        let bound = self.eval(bound)?;
+
+                        Ok(random_below(&mut *self.random_state, bound))
+                    }
+                    ("gcd", [lhs, rhs]) => {
+                        let lhs = self.eval(lhs)?;
+                        let rhs = self.eval(rhs)?;
+
+                        Ok(gcd(lhs, rhs))
+                    }
+                    ("wadd" | "wsub" | "wmul", [lhs, rhs]) => {
+                        // wrapping arithmetic on the operands truncated to 64-bit integers
+                        let lhs = self.eval(lhs)? as i64;
+                        let rhs = self.eval(rhs)? as i64;
+
+                        let result = match fn_name.as_str() {
+                            "wadd" => lhs.wrapping_add(rhs),
+                            "wsub" => lhs.wrapping_sub(rhs),
+                            _ => lhs.wrapping_mul(rhs),
+                        };
+
+                        Ok(result as f64)
+                    }
+                    ("max" | "min", [first, rest @ ..]) if !rest.is_empty() => {
+                        let mut lhs = self.eval(first)?;
+
+                        for rhs in rest {
+                            let rhs = self.eval(rhs)?;
+                            let cmp = if fn_name == "max" {
+                                lhs > rhs
+                            } else {
+                                lhs < rhs
+                            };
+
+                            lhs = if cmp { lhs } else { rhs };
+                        }
+
+                        Ok(lhs)
+                    }
+                    (
+                        "abs" | "round" | "floor" | "ceil" | "isqrt" | "rand" | "gcd" | "wadd"
+                        | "wsub" | "wmul" | "max" | "min",
+                        _,
+                    ) => Err(EvalError::Compile(
+                        "Wrong number of arguments passed to built-in function.",
+                    )),
+
+                    // a binary operator found where an operand was expected, as in '* 5' or '2 + * 3'
+                    (name, _) if name.strip_prefix("unary").is_some_and(is_binary_op_name) => Err(
+                        EvalError::Compile("Expected an operand before binary operator."),
+                    ),
+                    (name, _) if is_unary_op_name(name) => {
+                        Err(EvalError::Compile("Undefined unary operator."))
+                    }
+
+                    _ => Err(EvalError::Compile("Unknown function.")),
+                }
+            }
+
+            Expr::Conditional {
+                ref cond,
+                ref consequence,
+                ref alternative,
+            } => {
+                if is_true(self.eval(cond)?) {
+                    self.eval(consequence)
+                } else {
+                    self.eval(alternative)
+                }
+            }
+
+            Expr::For {
+                ref var_name,
+                ref start,
+                ref end,
+                ref step,
+                ref body,
+            } => {
+                let start = self.eval(start)?;
+                let old_val = self.variables.insert(var_name.clone(), start);
+
+                let result = self.eval_loop(var_name, end, step.as_deref(), body);
+
+                match old_val {
+                    Some(val) => self.variables.insert(var_name.clone(), val),
+                    None => self.variables.remove(var_name),
+                };
+
+                result.map(|()| 0.0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::builtin_precedences;
+
+    fn interpret_input(input: &str, env: &HashMap<String, f64>) -> Result<f64, EvalError> {
+        let fun = Parser::new(input.to_string(), &mut builtin_precedences())
+            .parse()
+            .unwrap();
+
+        interpret(fun.body.as_ref().unwrap(), env)
+    }
+
+    #[test]
+    fn interpret_with_env() {
+        let env = HashMap::from([("x".to_string(), 4.0)]);
+
+        assert_eq!(interpret_input("x * 2 + 1", &env).unwrap(), 9.0);
+        assert_eq!(interpret_input("(x = 1) + x", &env).unwrap(), 2.0);
+        assert_eq!(env["x"], 4.0);
+        assert!(matches!(
+            interpret_input("y", &env),
+            Err(EvalError::Compile(_))
+        ));
+    }
+
+    #[test]
+    fn interpret_loops_and_scopes() {
+        let env = HashMap::new();
+
+        assert_eq!(
+            interpret_input("var s = 0 in (for i = 1, i < 10 in s = s + i) + s", &env).unwrap(),
+            55.0
+        );
+        assert_eq!(
+            interpret_input("var a = 1 in (var a = 10 in a) + a", &env).unwrap(),
+            11.0
+        );
+        assert_eq!(interpret_input("0 and 1 / 0 or not 0", &env).unwrap(), 1.0);
+        assert_eq!(interpret_input("1 if 0 / 0 else 2", &env).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn trace_binary_operations() {
+        let mut globals = HashMap::new();
+        let mut random_state = random_state(0);
+        let mut prec = builtin_precedences();
+
+        let declarations = [
+            Parser::new("def binary~ 5 (a, b) a - b".to_string(), &mut prec)
+                .parse()
+                .unwrap(),
+        ];
+        let fun = Parser::new("2 + 3 * 4 ~ 1".to_string(), &mut prec)
+            .parse()
+            .unwrap();
+        let mut interpreter =
+            Interpreter::new(&declarations, &mut globals, &mut random_state).with_trace();
+
+        assert_eq!(interpreter.eval(fun.body.as_ref().unwrap()).unwrap(), 13.0);
+        assert_eq!(
+            interpreter.into_steps(),
+            ["3 * 4 = 12", "2 + 12 = 14", "14 - 1 = 13", "14 ~ 1 = 13"]
+        );
+    }
+
+    #[test]
+    fn interpret_constant_errors() {
+        let env = HashMap::from([("x".to_string(), 0.0)]);
+
+        for input in ["(-3)!", "2.5!", "isqrt(-1)", "1 % (2 - 2)"] {
+            assert!(matches!(
+                interpret_input(input, &env),
+                Err(EvalError::Compile(_))
+            ));
+        }
+
+        assert!(interpret_input("(x - 3)!", &env).unwrap().is_nan());
+        assert!(interpret_input("1 % x", &env).unwrap().is_nan());
+    }
+}
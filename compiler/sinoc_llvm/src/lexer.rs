@@ -0,0 +1,858 @@
+use std::iter::{Map, Peekable};
+use std::num::IntErrorKind;
+use std::ops::Range;
+use std::str::Chars;
+
+use crate::lexer::Token::*;
+
+/// Built-in operators spanning more than one character; all other operators are a single character.
+const MULTI_CHAR_OPERATORS: &[&str] = &["**", "//", "<<", ">>", "<=", ">=", "==", "!="];
+
+/// Error reported for a block comment lacking its closing '*/'.
+const UNTERMINATED_BLOCK_COMMENT: &str = "Unterminated block comment.";
+
+// ======================================================================================
+// LEXER ================================================================================
+// ======================================================================================
+
+/// Represents a primitive syntax token.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Binary,
+    Comma,
+    Comment,
+    Def,
+    Else,
+    EOF,
+    Extern,
+    For,
+    Ident(String),
+    If,
+    In,
+    LParen,
+    Number(f64),
+    Op(String),
+    RParen,
+    Then,
+    Unary,
+    Var,
+}
+
+/// Defines an error encountered by the `Lexer`, along with the index of the character
+/// it was encountered at, and the byte range of the text that failed to lex.
+#[allow(unused)]
+#[derive(Debug)]
+pub struct LexError {
+    pub error: &'static str,
+    pub index: usize,
+    pub span: Range<usize>,
+}
+
+impl LexError {
+    #[allow(unused)]
+    pub fn new(msg: &'static str) -> LexError {
+        LexError {
+            error: msg,
+            index: 0,
+            span: 0..0,
+        }
+    }
+
+    #[allow(unused)]
+    pub fn with_index(msg: &'static str, index: usize) -> LexError {
+        LexError {
+            error: msg,
+            index,
+            span: 0..0,
+        }
+    }
+}
+
+/// Removes the underscores separating groups of digits in a number literal,
+/// or returns `None` if one of them does not lie between two digits of the given radix.
+fn strip_separators(literal: &str, radix: u32) -> Option<String> {
+    let groups: Vec<&str> = literal.split('_').collect();
+    let is_digit = |ch: char| ch.is_digit(radix);
+
+    groups
+        .windows(2)
+        .all(|pair| pair[0].ends_with(is_digit) && pair[1].starts_with(is_digit))
+        .then(|| groups.concat())
+}
+
+/// Maps a full-width form, such as '＋' or '１', to the ASCII character it stands for.
+fn normalize_char(ch: char) -> char {
+    match ch {
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(ch as u32 - 0xFEE0).unwrap(),
+        _ => ch,
+    }
+}
+
+/// Defines the result of a lexing operation; namely a
+/// `Token` on success, or a `LexError` on failure.
+pub type LexResult = Result<Token, LexError>;
+
+/// Characters of an input with their full-width forms mapped to ASCII.
+type NormalizedChars<'a> = Peekable<Map<Chars<'a>, fn(char) -> char>>;
+
+/// Defines a lexer which transforms an input `String` into
+/// a `Token` stream.
+pub struct Lexer<'a> {
+    chars: NormalizedChars<'a>,
+    offsets: Vec<usize>,
+    pos: usize,
+    index: usize,
+}
+
+impl<'a> Lexer<'a> {
+    /// Creates a new `Lexer`, given its source `input`.
+    pub fn new(input: &'a str) -> Lexer<'a> {
+        Lexer {
+            chars: input
+                .chars()
+                .map(normalize_char as fn(char) -> char)
+                .peekable(),
+            offsets: input
+                .char_indices()
+                .map(|(offset, _)| offset)
+                .chain([input.len()])
+                .collect(),
+            pos: 0,
+            index: 0,
+        }
+    }
+
+    /// Returns the index of the character the last lexed `Token` starts at.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the byte range of the last lexed `Token` in the input,
+    /// or of the text that failed to lex.
+    pub fn span(&self) -> Range<usize> {
+        let offset = |index: usize| {
+            self.offsets
+                .get(index)
+                .copied()
+                .unwrap_or(*self.offsets.last().unwrap())
+        };
+
+        offset(self.index)..offset(self.pos)
+    }
+
+    /// Lexes and returns the next `Token` from the source code.
+    pub fn lex(&mut self) -> LexResult {
+        let chars = &mut self.chars;
+
+        let mut pos = self.pos;
+
+        // Skip whitespaces
+        loop {
+            // Note: the following lines are in their own scope to
+            // limit how long 'chars' is borrowed, and in order to allow
+            // it to be borrowed again in the loop by 'chars.next()'.
+            {
+                let ch = chars.peek();
+
+                if ch.is_none() {
+                    self.pos = pos;
+                    self.index = pos;
+
+                    return Ok(Token::EOF);
+                }
+
+                if !ch.unwrap().is_whitespace() {
+                    break;
+                }
+            }
+
+            chars.next();
+            pos += 1;
+        }
+
+        let start = pos;
+        let next = chars.next();
+
+        if next.is_none() {
+            return Ok(Token::EOF);
+        }
+
+        pos += 1;
+
+        // Actually get the next token.
+        let result = match next.unwrap() {
+            '(' => Ok(Token::LParen),
+            ')' => Ok(Token::RParen),
+            ',' => Ok(Token::Comma),
+
+            '#' => {
+                // Comment
+                loop {
+                    let ch = chars.next();
+                    pos += 1;
+
+                    if ch.is_none() || ch == Some('\n') {
+                        break;
+                    }
+                }
+
+                Ok(Token::Comment)
+            }
+
+            '/' if chars.peek() == Some(&'*') => {
+                // Block comment, which may span several lines
+                chars.next();
+                pos += 1;
+
+                let mut prev = None;
+
+                loop {
+                    let ch = chars.next();
+
+                    if ch.is_none() {
+                        break Err(LexError::with_index(UNTERMINATED_BLOCK_COMMENT, start));
+                    }
+
+                    pos += 1;
+
+                    if prev == Some('*') && ch == Some('/') {
+                        break Ok(Token::Comment);
+                    }
+
+                    prev = ch;
+                }
+            }
+
+            '0' if matches!(chars.peek(), Some('x' | 'o' | 'b')) => {
+                // Parse integer literal with a radix prefix
+                let radix = match chars.next() {
+                    Some('x') => 16,
+                    Some('o') => 8,
+                    _ => 2,
+                };
+
+                pos += 1;
+
+                let mut digits = String::new();
+
+                while let Some(&ch) = chars.peek() {
+                    // Consume every alphanumeric character, so that invalid digits are reported.
+                    if ch != '_' && !ch.is_ascii_alphanumeric() {
+                        break;
+                    }
+
+                    digits.push(ch);
+                    chars.next();
+                    pos += 1;
+                }
+
+                // valid digits may still denote a number too large for 64 bits
+                match strip_separators(&digits, radix)
+                    .map(|digits| u64::from_str_radix(&digits, radix))
+                {
+                    Some(Ok(nb)) => Ok(Token::Number(nb as f64)),
+                    Some(Err(err)) if *err.kind() == IntErrorKind::PosOverflow => Err(
+                        LexError::with_index("Integer literal too large for 64 bits.", start),
+                    ),
+                    _ => Err(LexError::with_index("Invalid integer literal.", start)),
+                }
+            }
+
+            first @ ('.' | '0'..='9') => {
+                // Parse number literal
+                let mut literal = first.to_string();
+
+                while let Some(&ch) = chars.peek() {
+                    // Consume every alphanumeric character, so that a literal such as '12abc'
+                    // is reported as a whole rather than followed by an identifier.
+                    if ch != '.' && ch != '_' && !ch.is_ascii_alphanumeric() {
+                        break;
+                    }
+
+                    literal.push(ch);
+                    chars.next();
+                    pos += 1;
+                }
+
+                match strip_separators(&literal, 10).and_then(|digits| digits.parse().ok()) {
+                    Some(nb) => Ok(Token::Number(nb)),
+                    None => Err(LexError::with_index("Invalid number literal.", start)),
+                }
+            }
+
+            first @ ('a'..='z' | 'A'..='Z' | '_') => {
+                // Parse identifier
+                let mut word = first.to_string();
+
+                while let Some(&ch) = chars.peek() {
+                    // A word-like identifier only contains underscores and alphanumeric characters.
+                    if ch != '_' && !ch.is_alphanumeric() {
+                        break;
+                    }
+
+                    word.push(ch);
+                    chars.next();
+                    pos += 1;
+                }
+
+                match word.as_str() {
+                    "def" => Ok(Token::Def),
+                    "extern" => Ok(Token::Extern),
+                    "if" => Ok(Token::If),
+                    "then" => Ok(Token::Then),
+                    "else" => Ok(Token::Else),
+                    "for" => Ok(Token::For),
+                    "in" => Ok(Token::In),
+                    "unary" => Ok(Token::Unary),
+                    "binary" => Ok(Token::Binary),
+                    "var" => Ok(Token::Var),
+
+                    // logical operators are spelled as keywords
+                    op @ ("and" | "or" | "not") => Ok(Token::Op(op.to_string())),
+
+                    ident => Ok(Token::Ident(ident.to_string())),
+                }
+            }
+
+            op if !op.is_ascii() => Err(LexError::with_index("Invalid character.", start)),
+
+            op => {
+                // Parse operator, preferring a built-in multi-character operator if one matches
+                let mut op = op.to_string();
+
+                if let Some(&ch) = chars.peek() {
+                    op.push(ch);
+
+                    if MULTI_CHAR_OPERATORS.contains(&op.as_str()) {
+                        chars.next();
+                        pos += 1;
+                    } else {
+                        op.pop();
+                    }
+                }
+
+                Ok(Token::Op(op))
+            }
+        };
+
+        // Update stored positions, and return
+        self.pos = pos;
+        self.index = start;
+
+        result.map_err(|err| LexError {
+            span: self.span(),
+            ..err
+        })
+    }
+}
+
+/// Lexes the whole `input`, failing on the first `LexError`.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, LexError> {
+    let tokens = tokenize_with_spans(input)?;
+
+    Ok(tokens.into_iter().map(|(token, _)| token).collect())
+}
+
+/// Lexes the whole `input` as `tokenize` does, along with the byte range of each token.
+pub fn tokenize_with_spans(input: &str) -> Result<Vec<(Token, Range<usize>)>, LexError> {
+    let mut lexer = Lexer::new(input);
+    let mut tokens = Vec::new();
+
+    loop {
+        match lexer.lex()? {
+            Token::EOF => return Ok(tokens),
+            token => tokens.push((token, lexer.span())),
+        }
+    }
+}
+
+/// Lexes the whole `input`, and lists its tokens one per line.
+pub fn dump_tokens(input: &str) -> Result<String, LexError> {
+    let tokens = tokenize(input)?;
+
+    Ok(tokens
+        .iter()
+        .map(|token| format!("{:?}\n", token))
+        .collect())
+}
+
+/// Returns a value indicating whether or not `input` leaves a parenthesis or a block
+/// comment open, in which case more input is needed to complete it.
+/// Inputs that otherwise fail to lex are never incomplete, so that the error gets reported.
+pub fn is_incomplete(input: &str) -> bool {
+    let tokens = match tokenize(input) {
+        Ok(tokens) => tokens,
+        Err(err) => return err.error == UNTERMINATED_BLOCK_COMMENT,
+    };
+
+    let mut depth = 0;
+
+    for token in tokens {
+        match token {
+            LParen => depth += 1,
+            RParen => depth -= 1,
+            _ => (),
+        }
+    }
+
+    depth > 0
+}
+
+/// Splits `input` into the statements separated by top-level ';', along with the
+/// index of the character each of them starts at. Semicolons within parentheses
+/// or comments, line or block ones, do not separate statements.
+///
+/// Statements may be blank, such as the one following a trailing ';'.
+pub fn split_statements(input: &str) -> Vec<(usize, &str)> {
+    let mut statements = Vec::new();
+    let mut depth = 0;
+    let mut in_comment = false;
+    let mut in_block_comment = false;
+    let mut prev = None;
+    let mut start = (0, 0);
+
+    for (index, (offset, ch)) in input.char_indices().enumerate() {
+        let curr = normalize_char(ch);
+        let mut delimiter = false;
+
+        match curr {
+            '/' if in_block_comment && prev == Some('*') => {
+                in_block_comment = false;
+                delimiter = true;
+            }
+            _ if in_block_comment => (),
+            '\n' => in_comment = false,
+            _ if in_comment => (),
+            '#' => in_comment = true,
+            '*' if prev == Some('/') => {
+                in_block_comment = true;
+                delimiter = true;
+            }
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ';' if depth <= 0 => {
+                statements.push((start.0, &input[start.1..offset]));
+                start = (index + 1, offset + ch.len_utf8());
+            }
+            _ => (),
+        }
+
+        // the characters opening or closing a block comment do not start another
+        // delimiter, as in '/*/'
+        prev = Some(curr).filter(|_| !delimiter);
+    }
+
+    statements.push((start.0, &input[start.1..]));
+    statements
+}
+
+/// Returns a value indicating whether or not `input` is only made of blanks and comments.
+/// Inputs that fail to lex are never blank, so that the error gets reported.
+pub fn is_blank(input: &str) -> bool {
+    match tokenize(input) {
+        Ok(tokens) => tokens.iter().all(|token| *token == Comment),
+        Err(_) => false,
+    }
+}
+
+/// Checks that `input` lexes without relying on lenient forms, as strict mode requires:
+/// decimal literals may not have leading zeros, as in `007`, and tokens may not use
+/// full-width forms, as in `１＋２`. Comments may contain anything.
+pub fn check_strict(input: &str) -> Result<(), LexError> {
+    for (token, span) in tokenize_with_spans(input)? {
+        if token == Comment {
+            continue;
+        }
+
+        let text = &input[span.clone()];
+        let mut chars = text.chars();
+
+        let error = if let Some(offset) = text.find(|ch| normalize_char(ch) != ch) {
+            let width = text[offset..].chars().next().unwrap().len_utf8();
+
+            Some((
+                "Full-width form in strict mode.",
+                span.start + offset..span.start + offset + width,
+            ))
+        } else if matches!(token, Number(_))
+            && chars.next() == Some('0')
+            && chars
+                .next()
+                .is_some_and(|ch| ch.is_ascii_digit() || ch == '_')
+        {
+            Some(("Leading zero in decimal literal in strict mode.", span))
+        } else {
+            None
+        };
+
+        if let Some((error, span)) = error {
+            return Err(LexError {
+                error,
+                index: input[..span.start].chars().count(),
+                span,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Token;
+
+    /// Lexes the next `Token` and returns it.
+    /// On EOF or failure, `None` will be returned.
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.lex() {
+            Ok(EOF) | Err(_) => None,
+            Ok(token) => Some(token),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_expression() {
+        let tokens = tokenize("1 + 2*3").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Number(1.0),
+                Op("+".to_string()),
+                Number(2.0),
+                Op("*".to_string()),
+                Number(3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_multi_char_operator() {
+        let tokens = tokenize("2 ** x*y // 3").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Number(2.0),
+                Op("**".to_string()),
+                Ident("x".to_string()),
+                Op("*".to_string()),
+                Ident("y".to_string()),
+                Op("//".to_string()),
+                Number(3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_comparison_operators() {
+        let tokens = tokenize("a<=b == c!=d").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Ident("a".to_string()),
+                Op("<=".to_string()),
+                Ident("b".to_string()),
+                Op("==".to_string()),
+                Ident("c".to_string()),
+                Op("!=".to_string()),
+                Ident("d".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_logical_operators() {
+        let tokens = tokenize("not a and b or android").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Op("not".to_string()),
+                Ident("a".to_string()),
+                Op("and".to_string()),
+                Ident("b".to_string()),
+                Op("or".to_string()),
+                Ident("android".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_unterminated_comment() {
+        let tokens = tokenize("def # comment").unwrap();
+
+        assert_eq!(tokens, vec![Def, Comment]);
+    }
+
+    #[test]
+    fn tokenize_block_comment() {
+        assert_eq!(
+            tokenize("1 /* x */ + 2").unwrap(),
+            vec![Number(1.0), Comment, Op("+".to_string()), Number(2.0)]
+        );
+        assert_eq!(
+            tokenize("4 /* a\n** b */ / 2 /*/ 3 */").unwrap(),
+            vec![
+                Number(4.0),
+                Comment,
+                Op("/".to_string()),
+                Number(2.0),
+                Comment
+            ]
+        );
+
+        let err = tokenize("1 /* x").unwrap_err();
+
+        assert_eq!(err.error, UNTERMINATED_BLOCK_COMMENT);
+        assert_eq!(err.index, 2);
+        assert_eq!(err.span, 2..6);
+    }
+
+    #[test]
+    fn split_statements_on_semicolons() {
+        assert_eq!(
+            split_statements("x = 2; y = 3;; x + y;"),
+            vec![
+                (0, "x = 2"),
+                (6, " y = 3"),
+                (13, ""),
+                (14, " x + y"),
+                (21, "")
+            ]
+        );
+        assert_eq!(
+            split_statements("f(1; 2) ; 3 # a; b\n; ４；5"),
+            vec![(0, "f(1; 2) "), (9, " 3 # a; b\n"), (20, " ４"), (23, "5")]
+        );
+        assert_eq!(split_statements(""), vec![(0, "")]);
+        assert_eq!(
+            split_statements("a /* ; */ ; b /*/ ; */"),
+            vec![(0, "a /* ; */ "), (11, " b /*/ ; */")]
+        );
+    }
+
+    #[test]
+    fn is_blank_input() {
+        assert!(is_blank(""));
+        assert!(is_blank(" \t# comment\n "));
+        assert!(!is_blank("1 # one"));
+        assert!(!is_blank("€"));
+    }
+
+    #[test]
+    fn tokenize_radix_literals() {
+        let tokens = tokenize("0xFF 0o17 0b1010 0 0.5").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Number(255.0),
+                Number(15.0),
+                Number(10.0),
+                Number(0.0),
+                Number(0.5),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_invalid_radix_literal() {
+        assert_eq!(tokenize("1 + 0xGG").unwrap_err().index, 4);
+        assert_eq!(tokenize("0b102").unwrap_err().index, 0);
+        assert_eq!(tokenize("0x").unwrap_err().index, 0);
+    }
+
+    #[test]
+    fn tokenize_leading_zeros() {
+        // only a letter after '0' selects another radix, so these are decimal
+        let tokens = tokenize("007 08 0_9 00.5").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![Number(7.0), Number(8.0), Number(9.0), Number(0.5)]
+        );
+    }
+
+    #[test]
+    fn check_strict_forms() {
+        for input in [
+            "7 + 0.5",
+            "0 + 0x0F + 0b01 + 0o07",
+            "1_000 # ０７",
+            "/* ＋ */ 1",
+        ] {
+            assert!(check_strict(input).is_ok(), "{}", input);
+        }
+
+        let err = check_strict("1 + 007").unwrap_err();
+
+        assert_eq!(err.error, "Leading zero in decimal literal in strict mode.");
+        assert_eq!((err.index, err.span), (4, 4..7));
+
+        let err = check_strict("1 ＋ 0_0").unwrap_err();
+
+        assert_eq!(err.error, "Full-width form in strict mode.");
+        assert_eq!((err.index, err.span), (2, 2..5));
+
+        assert!(check_strict("00.5").is_err());
+        assert!(check_strict("0_1").is_err());
+        assert!(check_strict("x１").is_err());
+    }
+
+    #[test]
+    fn tokenize_overflowing_radix_literal() {
+        let tokens = tokenize("0xFFFF_FFFF_FFFF_FFFF 0b1").unwrap();
+
+        assert_eq!(tokens, vec![Number(u64::MAX as f64), Number(1.0)]);
+
+        let err = tokenize("1 + 0x1_0000_0000_0000_0000").unwrap_err();
+
+        assert_eq!(err.error, "Integer literal too large for 64 bits.");
+        assert_eq!(err.index, 4);
+        assert_eq!(err.span, 4..27);
+
+        let err = tokenize("0x1_0000_0000_0000_000G").unwrap_err();
+
+        assert_eq!(err.error, "Invalid integer literal.");
+    }
+
+    #[test]
+    fn dump_expression_tokens() {
+        assert_eq!(
+            dump_tokens("2 + (x * 4)").unwrap(),
+            "Number(2.0)\nOp(\"+\")\nLParen\nIdent(\"x\")\nOp(\"*\")\nNumber(4.0)\nRParen\n"
+        );
+        assert_eq!(dump_tokens("2 + 0xZ").unwrap_err().index, 4);
+    }
+
+    #[test]
+    fn incomplete_input() {
+        assert!(is_incomplete("(1 + 2"));
+        assert!(is_incomplete("f((1, 2)\n"));
+        assert!(!is_incomplete("(1 + 2)"));
+        assert!(!is_incomplete("1 + 2)"));
+        assert!(!is_incomplete("1 # ("));
+        assert!(is_incomplete("1 /* x"));
+        assert!(!is_incomplete("1 /* ( */"));
+        assert!(!is_incomplete("(1 + 1a"));
+    }
+
+    #[test]
+    fn tokenize_digit_separators() {
+        let tokens = tokenize("1_000 1_0_0 1_000.000_1 0xFF_FF _1").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Number(1000.0),
+                Number(100.0),
+                Number(1000.0001),
+                Number(65535.0),
+                Ident("_1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_invalid_digit_separators() {
+        assert_eq!(tokenize("1 + 1_").unwrap_err().index, 4);
+        assert_eq!(tokenize("1__0").unwrap_err().index, 0);
+        assert_eq!(tokenize("1_.5").unwrap_err().index, 0);
+        assert_eq!(tokenize("0x_FF").unwrap_err().index, 0);
+        assert_eq!(tokenize("1e_5").unwrap_err().index, 0);
+        assert_eq!(tokenize("1_e5").unwrap_err().index, 0);
+        assert_eq!(tokenize("0xF_G").unwrap_err().index, 0);
+        assert_eq!(
+            tokenize("1e5 + 0x1_F").unwrap(),
+            tokenize("100000 + 31").unwrap()
+        );
+    }
+
+    #[test]
+    fn tokenize_after_multi_byte_character() {
+        let tokens = tokenize("ａé1 + aé").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                Ident("aé1".to_string()),
+                Op("+".to_string()),
+                Ident("aé".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_full_width_forms() {
+        let tokens = tokenize("（１＋２）＊x").unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                LParen,
+                Number(1.0),
+                Op("+".to_string()),
+                Number(2.0),
+                RParen,
+                Op("*".to_string()),
+                Ident("x".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_invalid_character() {
+        assert_eq!(tokenize("1 + 三").unwrap_err().index, 4);
+        assert_eq!(tokenize("1 + 三").unwrap_err().span, 4..7);
+        assert_eq!(tokenize("é1").unwrap_err().index, 0);
+        assert_eq!(tokenize("é1").unwrap_err().span, 0..2);
+    }
+
+    #[test]
+    fn tokenize_spans() {
+        assert_eq!(
+            tokenize_with_spans("12 + 345").unwrap(),
+            vec![
+                (Number(12.0), 0..2),
+                (Op("+".to_string()), 3..4),
+                (Number(345.0), 5..8),
+            ]
+        );
+        assert_eq!(
+            tokenize_with_spans("１２ <= x # x\n").unwrap(),
+            vec![
+                (Number(12.0), 0..6),
+                (Op("<=".to_string()), 7..9),
+                (Ident("x".to_string()), 10..11),
+                (Comment, 12..16),
+            ]
+        );
+        assert_eq!(tokenize("1 + 0xGG").unwrap_err().span, 4..8);
+    }
+
+    #[test]
+    fn tokenize_invalid_number() {
+        let err = tokenize("1 + 1a").unwrap_err();
+
+        assert_eq!(err.index, 4);
+
+        for (input, span) in [
+            ("12abc", 0..5),
+            ("12xyz + 1", 0..5),
+            ("3.5kg", 0..5),
+            ("0x", 0..2),
+        ] {
+            let err = tokenize(input).unwrap_err();
+
+            assert_eq!(err.index, 0, "{}", input);
+            assert_eq!(err.span, span, "{}", input);
+        }
+    }
+}
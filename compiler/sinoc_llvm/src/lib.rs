@@ -0,0 +1,86 @@
+//! The Sino language: a lexer, a parser and an LLVM-based compiler, together with
+//! a `Session` that JIT-executes successive inputs as the REPL does, or evaluates
+//! them with a tree-walking interpreter where the JIT is unavailable.
+//!
+//! Both the `Parser` and the `Compiler` may fail. The `Parser` returns a `ParseError`
+//! holding a message along with the position of the offending input, and the `Compiler`
+//! an error represented by `Result<T, &'static str>`, for easier error reporting.
+//! A `Session` tags these errors with the stage that failed in an `EvalError`.
+
+use std::collections::HashMap;
+
+use inkwell::context::Context;
+
+mod implementation_typed_pointers;
+mod interpreter;
+mod lexer;
+mod session;
+
+pub use crate::implementation_typed_pointers::*;
+pub use crate::interpreter::interpret;
+pub use crate::lexer::{
+    check_strict, dump_tokens, is_blank, is_incomplete, split_statements, tokenize,
+    tokenize_with_spans, LexError, LexResult, Lexer, Token,
+};
+pub use crate::session::{EvalError, Session, BINARY_OPERATORS};
+
+use crate::session::builtin_precedences;
+
+/// Evaluates a single top-level expression in a fresh `Session`.
+pub fn eval(input: &str) -> Result<f64, EvalError> {
+    let context = Context::create();
+    let mut session = Session::new(&context);
+
+    session.run(input)?.ok_or_else(|| not_an_expression(input))
+}
+
+/// Evaluates a single top-level expression in which the given variables are defined,
+/// with the interpreter, so that no LLVM context or JIT is needed. Names neither
+/// in `vars` nor built in fail to evaluate, and assignments to `vars` are not kept.
+pub fn eval_with_vars(input: &str, vars: &HashMap<String, f64>) -> Result<f64, EvalError> {
+    let mut prec = builtin_precedences();
+    let fun = Parser::new(input.to_string(), &mut prec)
+        .parse()
+        .map_err(EvalError::Parse)?;
+
+    match fun.body {
+        Some(body) if fun.is_anon => interpret(&body, vars),
+        _ => Err(not_an_expression(input)),
+    }
+}
+
+/// Returns the error of an input expected to be an expression but which is a definition.
+fn not_an_expression(input: &str) -> EvalError {
+    EvalError::Parse(ParseError {
+        error: "Expected an expression, found a definition.",
+        index: 0,
+        span: 0..input.len(),
+    })
+}
+
+/// Checks that a single top-level expression or definition parses, without compiling it,
+/// so that no LLVM context or JIT is needed. Binary operators defined in `input` only
+/// apply to the rest of `input`.
+pub fn check(input: &str) -> Result<(), EvalError> {
+    let mut prec = builtin_precedences();
+
+    Parser::new(input.to_string(), &mut prec)
+        .parse()
+        .map(|_| ())
+        .map_err(EvalError::Parse)
+}
+
+/// Evaluates every line in order in a single `Session`, so that later lines may use
+/// the definitions of earlier ones, and returns the result of each line as `run_program`
+/// does. Lines failing to evaluate do not prevent the following ones from running.
+pub fn eval_lines<'a>(
+    context: &Context,
+    lines: impl IntoIterator<Item = &'a str>,
+) -> Vec<Result<Option<f64>, EvalError>> {
+    let mut session = Session::new(context);
+
+    lines
+        .into_iter()
+        .map(|line| session.run_program(line))
+        .collect()
+}
@@ -2,35 +2,31 @@
 //! made in Rust, using Inkwell.
 //! Currently, all features up to the [7th chapter](https://llvm.org/docs/tutorial/LangImpl07.html)
 //! are available.
-//! This example is supposed to be ran as a executable, which launches a REPL.
-//! The source code is in the following order:
+//! This example is supposed to be ran as a executable, which launches a REPL,
+//! or runs the inputs given with `-e`, or the script whose path is given as an argument,
+//! or the input in the `SINO_EXPR` environment variable, or the piped standard input.
+//! With `--emit-obj <path> <input>`, the input is compiled to an object file instead,
+//! and with `--emit-llvm <path> <input>`, to a file of textual LLVM IR.
+//! The language itself lives in the `sinoc_llvm` library, in the following order:
 //! - Lexer,
 //! - Parser,
 //! - Compiler,
-//! - Program.
-//!
-//! Both the `Parser` and the `Compiler` may fail, in which case they would return
-//! an error represented by `Result<T, &'static str>`, for easier error reporting.
+//! - Interpreter,
+//! - Session.
 
-use std::collections::HashMap;
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{env, fs, process};
 
 use inkwell::context::Context;
-use inkwell::module::Module;
-// #[llvm_versions(4.0..=15.0)]
-// use inkwell::passes::PassManager;
 use inkwell::OptimizationLevel;
-// #[llvm_versions(16.0..=latest)]
-use inkwell::{
-    passes::PassBuilderOptions,
-    targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetMachine},
-};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
 
-// use inkwell_internals::llvm_versions;
-
-mod implementation_typed_pointers;
-
-use crate::implementation_typed_pointers::*;
+use sinoc_llvm::{dump_tokens, is_incomplete, EvalError, Session, BINARY_OPERATORS};
 
 // ======================================================================================
 // PROGRAM ==============================================================================
@@ -62,177 +58,1149 @@ pub extern "C" fn printd(x: f64) -> f64 {
 #[used]
 static EXTERNAL_FNS: [extern "C" fn(f64) -> f64; 2] = [putchard, printd];
 
-// #[llvm_versions(4.0..=15.0)]
-// fn run_passes_on(module: &Module) {
-//     let fpm = PassManager::create(());
-
-//     fpm.add_instruction_combining_pass();
-//     fpm.add_reassociate_pass();
-//     fpm.add_gvn_pass();
-//     fpm.add_cfg_simplification_pass();
-//     fpm.add_basic_alias_analysis_pass();
-//     fpm.add_promote_memory_to_register_pass();
-
-//     fpm.run_on(module);
-// }
-
-// #[llvm_versions(16.0..=latest)]
-fn run_passes_on(module: &Module) {
-    Target::initialize_all(&InitializationConfig::default());
-    let target_triple = TargetMachine::get_default_triple();
-    let target = Target::from_triple(&target_triple).unwrap();
-    let target_machine = target
-        .create_target_machine(
-            &target_triple,
-            "generic",
-            "",
-            OptimizationLevel::None,
-            RelocMode::PIC,
-            CodeModel::Default,
-        )
-        .unwrap();
-
-    let passes: &[&str] = &[
-        "instcombine",
-        "reassociate",
-        "gvn",
-        "simplifycfg",
-        // "basic-aa",
-        "mem2reg",
-    ];
-
-    module
-        .run_passes(
-            passes.join(",").as_str(),
-            &target_machine,
-            PassBuilderOptions::create(),
+/// Command-line options, along with their description.
+const OPTIONS: &[(&str, &str)] = &[
+    ("--dl", "display the output of the lexer"),
+    ("--dp", "display the output of the parser"),
+    ("--dc", "display the output of the compiler"),
+    ("-e <input>", "run <input> like a script line, repeatably"),
+    ("--emit-obj <path>", "compile <input> to an object file"),
+    ("--emit-llvm <path>", "compile <input> to textual LLVM IR"),
+    (
+        "--opt <level>",
+        "optimize machine code: none (default), less, default, aggressive",
+    ),
+    ("--interp", "interpret inputs instead of compiling them"),
+    ("--trace", "interpret inputs, printing every operation"),
+    ("--json", "print results and errors as lines of JSON"),
+    ("--strict", "reject leading zeros and full-width forms"),
+    ("--keep-going", "run the rest of a script after an error"),
+    ("-q, --quiet", "start the REPL without printing the banner"),
+    ("--prompt <text>", "prompt with <text> in the REPL"),
+    ("--version", "print the version and exit"),
+    ("--help", "print this help and exit"),
+];
+
+/// Commands understood by the REPL, along with their description.
+const COMMANDS: &[(&str, &str)] = &[
+    ("help", "print this reference"),
+    ("exit, quit", "leave the REPL"),
+    ("clear", "forget every variable, function and operator"),
+    (":ir <input>", "print the LLVM IR of an input"),
+    (":tokens <input>", "print the tokens of an input"),
+    (":time <input>", "run an input and print how long it took"),
+    (
+        ":watch <ms> <input>",
+        "run an input every <ms> until Ctrl+C",
+    ),
+    (":seed <n>", "seed rand(n), so that its results repeat"),
+    (":base dec|hex|bin", "print integer results in that base"),
+    (":percent", "toggle a trailing % dividing by 100"),
+    (":echo on|off", "print inputs grouped before their result"),
+    (":settings", "print the settings changed by commands"),
+    (":reset [setting]", "reset one or every setting"),
+    (":store <n>", "save the last result, read back as M<n>"),
+    (":vars", "print the variables defined so far"),
+    (":save <path>", "write the inputs run so far to a script"),
+];
+
+/// Defines the settings of the REPL changed by its commands, as printed by `:settings`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Settings {
+    base: Base,
+    percent: bool,
+    echo: bool,
+}
+
+impl Settings {
+    /// Names of the settings, as accepted by `:reset`.
+    const NAMES: &'static [&'static str] = &["base", "percent", "echo"];
+
+    /// Resets the setting of the given name to its default value, or every setting
+    /// if `name` is empty. Returns `false` if there is no such setting.
+    fn reset(&mut self, name: &str) -> bool {
+        let default = Settings::default();
+
+        match name {
+            "" => *self = default,
+            "base" => self.base = default.base,
+            "percent" => self.percent = default.percent,
+            "echo" => self.echo = default.echo,
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Sets the base of the given name, as `:base` does. Returns `false` if there is
+    /// no such base.
+    fn set_base(&mut self, name: &str) -> bool {
+        match Base::from_name(name) {
+            Some(base) => self.base = base,
+            None => return false,
+        }
+
+        true
+    }
+
+    /// Formats every setting along with its value, one per line.
+    fn describe(&self) -> String {
+        let on_off = |on| if on { "on" } else { "off" };
+
+        format!(
+            "  base = {}\n  percent = {}\n  echo = {}\n",
+            self.base.name(),
+            on_off(self.percent),
+            on_off(self.echo)
         )
-        .unwrap();
+    }
+}
+
+/// Set when Ctrl+C is pressed while an input is being evaluated.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Longest time `:watch` waits between two checks of `INTERRUPTED`.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Defines the base the REPL prints integer results in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Base {
+    #[default]
+    Dec,
+    Hex,
+    Bin,
+}
+
+impl Base {
+    /// Returns the name of this base, as accepted by `:base`.
+    fn name(self) -> &'static str {
+        match self {
+            Base::Dec => "dec",
+            Base::Hex => "hex",
+            Base::Bin => "bin",
+        }
+    }
+
+    /// Returns the base of the given name, as accepted by `:base`.
+    fn from_name(name: &str) -> Option<Base> {
+        match name {
+            "dec" => Some(Base::Dec),
+            "hex" => Some(Base::Hex),
+            "bin" => Some(Base::Bin),
+            _ => None,
+        }
+    }
+
+    /// Formats the given result in this base. Integers whose magnitude is below 2^64 are
+    /// written as their magnitude with a leading '-' when negative, as in `-0xff`, while
+    /// other numbers are always written in decimal.
+    fn format(self, value: f64) -> String {
+        let bound = 2f64.powi(64);
+
+        if self == Base::Dec || value.fract() != 0.0 || value.abs() >= bound {
+            return value.to_string();
+        }
+
+        let sign = if value < 0.0 { "-" } else { "" };
+        let magnitude = value.abs() as u64;
+
+        match self {
+            Base::Hex => format!("{}0x{:x}", sign, magnitude),
+            _ => format!("{}0b{:b}", sign, magnitude),
+        }
+    }
+}
+
+/// Returns a short reference of the supported syntax and of the REPL commands.
+fn help() -> String {
+    let mut help = String::from("Binary operators, from the loosest to the tightest:\n");
+
+    for ops in BINARY_OPERATORS.chunk_by(|(_, a), (_, b)| a == b) {
+        let ops: Vec<&str> = ops.iter().map(|&(op, _)| op).collect();
+
+        help.push_str(&format!("  {}\n", ops.join(" ")));
+    }
+
+    help.push_str(concat!(
+        "Unary operators: - + not\n",
+        "Postfix operators: ! (factorial)\n",
+        "Built-in functions: abs(x), max(x, y, ...), min(x, y, ...),\n",
+        "  isqrt(x), failing on a constant negative x, and NaN on a runtime one,\n",
+        "  round(x), floor(x), ceil(x), rounding to integers as floats,\n",
+        "  gcd(x, y), rand(n), a random integer from 0 to n - 1,\n",
+        "  wadd(x, y), wsub(x, y), wmul(x, y), wrapping on 64-bit integers\n",
+        "Constants: pi, e\n",
+        "Number literals: 42, 3.14, 1_000, 0xFF, 0o17, 0b1010\n",
+        "Expressions: ( ), a if c else b, if c then a else b,\n",
+        "  for i = start, end, step in body, var x = init in body\n",
+        "Definitions: def f(x) body, extern f(x), def binary op prec (a, b) body,\n",
+        "  def unary op (a) body, and top-level assignments such as x = 1\n",
+        "Statements: several inputs on a line are separated by ;\n",
+        "Commands:\n",
+    ));
+
+    for (command, description) in COMMANDS {
+        help.push_str(&format!("  {:<16} {}\n", command, description));
+    }
+
+    help
+}
+
+/// Returns the version line printed on startup.
+fn version() -> String {
+    format!(
+        "Sino {} (main, Dec  8 2023, 18:56:58) [GCC 11.4.0] on {}",
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS
+    )
+}
+
+/// Returns the command-line usage of the program.
+fn usage() -> String {
+    let mut usage = String::from(concat!(
+        "Usage: sino [options] [script]\n",
+        "       sino -e <input>...\n",
+        "       sino --emit-obj <path> <input>\n",
+        "       sino --emit-llvm <path> <input>\n",
+        "\n",
+        "Runs the inputs given with -e, or the given script, or the input in the SINO_EXPR\n",
+        "environment variable, or the piped standard input, or else starts a REPL.\n",
+        "The REPL prompt may also be set with the SINO_PROMPT environment variable.\n",
+        "\n",
+        "Options:\n",
+    ));
+
+    for (option, description) in OPTIONS {
+        usage.push_str(&format!("  {:<18} {}\n", option, description));
+    }
+
+    usage
+}
+
+/// Runs `input` every `interval`, passing each result to `report`, until it fails
+/// or Ctrl+C is pressed. Returns `true` if it was stopped by Ctrl+C.
+fn watch(
+    session: &mut Session,
+    interval: Duration,
+    input: &str,
+    mut report: impl FnMut(&Result<Option<f64>, EvalError>),
+) -> bool {
+    loop {
+        let start = Instant::now();
+        let result = session.run_program(input);
+
+        report(&result);
+
+        if result.is_err() {
+            return false;
+        }
+
+        // sleep in short steps, so that Ctrl+C stops the loop right away
+        loop {
+            if INTERRUPTED.swap(false, Ordering::SeqCst) {
+                return true;
+            }
+
+            let remaining = interval.saturating_sub(start.elapsed());
+
+            if remaining.is_zero() {
+                break;
+            }
+
+            thread::sleep(remaining.min(WATCH_POLL_INTERVAL));
+        }
+    }
+}
+
+/// Formats the given duration in the largest unit in which it is at least 1.
+fn format_duration(duration: Duration) -> String {
+    let nanos = duration.as_nanos();
+
+    if nanos < 1_000 {
+        format!("{} ns", nanos)
+    } else if nanos < 1_000_000 {
+        format!("{:.2} µs", nanos as f64 / 1e3)
+    } else if nanos < 1_000_000_000 {
+        format!("{:.2} ms", nanos as f64 / 1e6)
+    } else {
+        format!("{:.2} s", duration.as_secs_f64())
+    }
+}
+
+/// Returns a value indicating whether or not errors written to `stream` are colored,
+/// which they are on a terminal, unless the `NO_COLOR` environment variable is set.
+fn use_color(stream: &impl IsTerminal) -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+
+    stream.is_terminal() && !no_color
+}
+
+/// Formats the given error message, in red if `color` is set.
+fn paint_error(message: impl std::fmt::Display, color: bool) -> String {
+    if color {
+        format!("\x1b[31m{}\x1b[0m", message)
+    } else {
+        message.to_string()
+    }
+}
+
+/// Writes the given inputs to the script at `path`, one per line, so that running
+/// it replays them. Inputs spanning several lines are joined into one.
+fn save_inputs(path: &str, inputs: &[String]) -> io::Result<()> {
+    let script: String = inputs
+        .iter()
+        .map(|input| format!("{}\n", input.replace('\n', " ")))
+        .collect();
+
+    fs::write(path, script)
+}
+
+/// Returns the prompt of the REPL: the one given with `--prompt`, or else the value
+/// of the `SINO_PROMPT` environment variable, or else `>>>`.
+fn prompt(option: Option<String>) -> String {
+    option
+        .or_else(|| env::var("SINO_PROMPT").ok())
+        .unwrap_or_else(|| String::from(">>>"))
 }
 
-/// Entry point of the program; acts as a REPL.
+/// Returns the prompt continuing an incomplete input, as wide as `prompt`,
+/// with dots in place of its visible characters.
+fn continuation_prompt(prompt: &str) -> String {
+    prompt
+        .chars()
+        .map(|c| if c.is_whitespace() { c } else { '.' })
+        .collect()
+}
+
+/// Returns the path of the file the REPL history is kept in, if the home directory is known.
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".sino_history"))
+}
+
+/// Reads a single line with the given prompt, adding it to the history.
+/// Fails with `ReadlineError::Eof` or `ReadlineError::Interrupted` when
+/// the input has been closed or interrupted.
+fn read_line(editor: &mut DefaultEditor, prompt: &str) -> Result<String, ReadlineError> {
+    match editor.readline(prompt) {
+        Ok(line) => {
+            if !line.trim().is_empty() {
+                let _ = editor.add_history_entry(line.as_str());
+            }
+
+            Ok(line)
+        }
+
+        Err(err @ (ReadlineError::Eof | ReadlineError::Interrupted)) => Err(err),
+        Err(err) => panic!("Could not read from standard input: {}", err),
+    }
+}
+
+/// Formats `text` as a JSON string literal.
+fn json_string(text: &str) -> String {
+    let mut json = String::from('"');
+
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+/// Formats the result of the input at the given line as a JSON object.
+/// Values which JSON cannot represent, `inf`, `-inf` and `NaN`, are written as strings.
+fn json_result(line: usize, result: &Result<Option<f64>, EvalError>) -> String {
+    match result {
+        Ok(Some(value)) if value.is_finite() => format!("{{\"ok\":true,\"value\":{}}}", value),
+        Ok(Some(value)) => format!(
+            "{{\"ok\":true,\"value\":{}}}",
+            json_string(&value.to_string())
+        ),
+        Ok(None) => String::from("{\"ok\":true}"),
+        Err(err) => format!(
+            "{{\"ok\":false,\"error\":{},\"kind\":\"{}\",\"line\":{}}}",
+            json_string(&err.to_string()),
+            err.kind(),
+            line
+        ),
+    }
+}
+
+/// Runs every line of the script at `path`, as `run_source` does.
+fn run_script(session: &mut Session, path: &str, json: bool, keep_going: bool) -> ! {
+    match fs::read_to_string(path) {
+        Ok(source) => run_source(session, path, &source, json, keep_going),
+        Err(err) => {
+            eprintln!("Could not read '{}': {}", path, err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs every line of the standard input, as `run_source` does.
+fn run_stdin(session: &mut Session, json: bool, keep_going: bool) -> ! {
+    match io::read_to_string(io::stdin()) {
+        Ok(source) => run_source(session, "<stdin>", &source, json, keep_going),
+        Err(err) => {
+            eprintln!("Could not read from standard input: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs every line of `source`, named `name` in error messages, printing the value
+/// of each line, that is of its last statement. Blank lines and lines starting
+/// with '#' are skipped. With `json` set, the result of every line, including
+/// definitions and errors, is instead printed as a JSON object on the standard output.
+/// A line starting with `return` stops the script after running the rest of the line.
+///
+/// The process exits with the value of the last expression truncated to an integer,
+/// of which only the lowest 8 bits are kept (so `256` exits with 0 and `-1` with 255),
+/// or with 0 if there is none, or if a `return` line has no value. It exits with 1
+/// on the first error, unless `keep_going` is set, in which case the following lines
+/// still run and it exits with 1 once done.
+fn run_source(session: &mut Session, name: &str, source: &str, json: bool, keep_going: bool) -> ! {
+    let mut last = None;
+    let mut failed = false;
+    let mut lines = script_lines(source).enumerate();
+
+    while let Some((i, line)) = lines.next() {
+        let mut input = line.trim().to_string();
+
+        // skip blank lines and comment lines, which the parser would reject as empty
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
+
+        // an input leaving a parenthesis or a block comment open goes on over the next lines,
+        // and errors are reported at the line it starts on
+        while is_incomplete(&input) {
+            match lines.next() {
+                Some((_, line)) => {
+                    input.push('\n');
+                    input.push_str(line.trim());
+                }
+                None => break,
+            }
+        }
+
+        // blank out the keyword, so that errors are still reported at the right column
+        let returning = is_return(&input);
+
+        if returning {
+            input.replace_range(..RETURN_KEYWORD.len(), &" ".repeat(RETURN_KEYWORD.len()));
+        }
+
+        let result = session.run_program(&input);
+
+        if json {
+            println!("{}", json_result(i + 1, &result));
+        }
+
+        match &result {
+            Ok(Some(value)) => {
+                if !json {
+                    println!("{}", value);
+                }
+
+                last = Some(*value);
+            }
+            Ok(None) => (),
+            Err(err) => {
+                if !json {
+                    let message = format!("{}:{}: {}", name, i + 1, err);
+
+                    eprintln!("{}", paint_error(message, use_color(&io::stderr())));
+                }
+
+                if !keep_going {
+                    process::exit(1);
+                }
+
+                failed = true;
+            }
+        }
+
+        // the value of the line is the result, even if it has none
+        if returning {
+            last = result.unwrap_or(None);
+            break;
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+
+    process::exit(last.map_or(0, |value| (value as i64 & 0xFF) as i32));
+}
+
+/// Keyword starting a script line which stops the script, with the value of the line.
+const RETURN_KEYWORD: &str = "return";
+
+/// Returns whether `line` starts with the `return` keyword.
+fn is_return(line: &str) -> bool {
+    line.strip_prefix(RETURN_KEYWORD)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Splits `source` into lines ending in `\n`, `\r\n` or a lone `\r`, without their line endings.
+fn script_lines(source: &str) -> impl Iterator<Item = &str> {
+    source.lines().flat_map(|line| line.split('\r'))
+}
+
+/// Returns the optimization level of the given name, as accepted by `--opt`.
+fn optimization_level(name: &str) -> Option<OptimizationLevel> {
+    match name {
+        "none" => Some(OptimizationLevel::None),
+        "less" => Some(OptimizationLevel::Less),
+        "default" => Some(OptimizationLevel::Default),
+        "aggressive" => Some(OptimizationLevel::Aggressive),
+        _ => None,
+    }
+}
+
+/// Kinds of files an input is compiled to instead of being run.
+#[derive(Clone, Copy)]
+enum Emit {
+    Object,
+    Llvm,
+}
+
+impl Emit {
+    /// Returns the command-line option selecting this kind of file.
+    fn option(self) -> &'static str {
+        match self {
+            Emit::Object => "--emit-obj",
+            Emit::Llvm => "--emit-llvm",
+        }
+    }
+}
+
+/// Compiles `input` to a file of the given kind at `path`, then exits with a non-zero code
+/// on error.
+fn emit_file(session: &mut Session, kind: Emit, input: &str, path: &str) -> ! {
+    let result = match kind {
+        Emit::Object => session.emit_object(input, Path::new(path)),
+        Emit::Llvm => session.emit_llvm(input, Path::new(path)),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{}", paint_error(err, use_color(&io::stderr())));
+        process::exit(1);
+    }
+
+    process::exit(0);
+}
+
+/// Entry point of the program; acts as a REPL, or runs a script or the piped input.
 pub fn main() {
-    // use self::inkwell::support::add_symbol;
-    let mut display_lexer_output = false;
-    let mut display_parser_output = false;
-    let mut display_compiler_output = false;
+    let mut args = env::args().skip(1);
+    let mut display = (false, false, false);
+    let mut opt_level = OptimizationLevel::None;
+    let mut quiet = false;
+    let mut interpret = false;
+    let mut trace = false;
+    let mut json = false;
+    let mut strict = false;
+    let mut keep_going = false;
+    let mut custom_prompt = None;
+    let mut emit = None;
+    let mut inputs = Vec::new();
+    let mut positional = None;
 
-    for arg in std::env::args() {
+    // use self::inkwell::support::add_symbol;
+    while let Some(arg) = args.next() {
         match arg.as_str() {
-            "--dl" => display_lexer_output = true,
-            "--dp" => display_parser_output = true,
-            "--dc" => display_compiler_output = true,
-            _ => (),
+            "--dl" => display.0 = true,
+            "--dp" => display.1 = true,
+            "--dc" => display.2 = true,
+            "--interp" => interpret = true,
+            "--trace" => trace = true,
+            "--json" => json = true,
+            "--strict" => strict = true,
+            "--keep-going" => keep_going = true,
+            "-q" | "--quiet" => quiet = true,
+            "--opt" => match args.next().as_deref().map(optimization_level) {
+                Some(Some(level)) => opt_level = level,
+                _ => {
+                    eprintln!("Expected one of none, less, default or aggressive after '--opt'.");
+                    process::exit(2);
+                }
+            },
+            "--prompt" => match args.next() {
+                Some(text) => custom_prompt = Some(text),
+                None => {
+                    eprintln!("Expected a prompt after '--prompt'.");
+                    process::exit(2);
+                }
+            },
+            "-e" => match args.next() {
+                Some(input) => inputs.push(input),
+                None => {
+                    eprintln!("Expected an input after '-e'.");
+                    process::exit(2);
+                }
+            },
+            "--emit-obj" | "--emit-llvm" => match args.next() {
+                Some(path) if arg == "--emit-obj" => emit = Some((Emit::Object, path)),
+                Some(path) => emit = Some((Emit::Llvm, path)),
+                None => {
+                    eprintln!("Expected a path after '{}'.", arg);
+                    process::exit(2);
+                }
+            },
+            "--version" => {
+                println!("{}", version());
+                process::exit(0);
+            }
+            "--help" => {
+                print!("{}", usage());
+                process::exit(0);
+            }
+            _ if !arg.starts_with("--") => positional = Some(arg),
+            _ => {
+                eprintln!("Unknown option '{}'; try '--help'.", arg);
+                process::exit(2);
+            }
         }
     }
 
     let context = Context::create();
-    let builder = context.create_builder();
+    let mut session = Session::with_optimization_level(&context, opt_level);
 
-    let mut previous_exprs = Vec::new();
-    println!("Sino 0.0.2 (main, Dec  8 2023, 18:56:58) [GCC 11.4.0] on linux");
-    println!("Type \"help\", \"copyright\", \"credits\" or \"license\" for more information.");
-    loop {
-        // println!();
-        print_flush!(">>>");
+    session.display_lexer_output = display.0;
+    session.display_parser_output = display.1;
+    session.display_compiler_output = display.2;
+    session.interpret |= interpret;
+    session.trace = trace;
+    session.strict = strict;
 
-        // Read input from stdin
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Could not read from standard input.");
+    if let Some((kind, path)) = emit {
+        match positional {
+            Some(input) => emit_file(&mut session, kind, &input, &path),
+            None => {
+                eprintln!(
+                    "Expected an input to compile after '{} <path>'.",
+                    kind.option()
+                );
+                process::exit(1);
+            }
+        }
+    }
+
+    // run the inputs given on the command line as the lines of a script
+    if !inputs.is_empty() {
+        run_source(&mut session, "-e", &inputs.join("\n"), json, keep_going);
+    }
+
+    if let Some(path) = positional {
+        run_script(&mut session, &path, json, keep_going);
+    }
+
+    // run the input set in the environment, as container entrypoints may do
+    if let Some(input) = env::var("SINO_EXPR").ok().filter(|input| !input.is_empty()) {
+        run_source(&mut session, "SINO_EXPR", &input, json, keep_going);
+    }
+
+    // run piped input quietly, without banner nor prompts
+    if !io::stdin().is_terminal() {
+        run_stdin(&mut session, json, keep_going);
+    }
+
+    if !quiet {
+        println!("{}", version());
+        println!("Type \"help\", \"copyright\", \"credits\" or \"license\" for more information.");
+    }
+
+    // Ctrl+C only raises a signal while an input is evaluated, since the line editor
+    // reports it as an error otherwise; compiled code cannot be stopped, so a second
+    // interrupt exits
+    ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            println!("\nKeyboardInterrupt");
+            process::exit(130);
+        }
+    })
+    .expect("Could not set the Ctrl+C handler.");
+
+    let color = use_color(&io::stdout());
+    let mut settings = Settings::default();
+    let mut accepted = Vec::new();
+    let mut editor = DefaultEditor::new().expect("Could not create the line editor.");
+    let history = history_path();
+    let prompt = prompt(custom_prompt);
+    let continuation = continuation_prompt(&prompt);
+
+    if let Some(ref path) = history {
+        // there is no history yet on the first run
+        let _ = editor.load_history(path);
+    }
+
+    loop {
+        // Read input from stdin, exiting on EOF
+        let mut input = match read_line(&mut editor, &prompt) {
+            Ok(input) => input,
+            Err(ReadlineError::Interrupted) => {
+                println!("KeyboardInterrupt");
+                continue;
+            }
+            Err(_) => {
+                println!();
+                break;
+            }
+        };
 
         if input.starts_with("exit") || input.starts_with("quit") {
             break;
-        } else if input.chars().all(char::is_whitespace) {
+        } else if input.chars().all(char::is_whitespace) || input.trim_start().starts_with('#') {
+            continue;
+        } else if input.trim() == "help" {
+            print!("{}", help());
             continue;
+        } else if input.trim() == "clear" {
+            session.clear();
+            accepted.clear();
+            println!("Cleared every definition.");
+            continue;
+        }
+
+        // keep reading while a parenthesis or a block comment is left open,
+        // until a blank line is entered
+        let mut interrupted = false;
+
+        while is_incomplete(&input) {
+            match read_line(&mut editor, &continuation) {
+                Ok(line) if !line.chars().all(char::is_whitespace) => {
+                    input.push('\n');
+                    input.push_str(&line);
+                }
+
+                Err(ReadlineError::Interrupted) => {
+                    interrupted = true;
+                    break;
+                }
+
+                _ => break,
+            }
         }
 
-        // Build precedence map
-        let mut prec = HashMap::with_capacity(6);
+        // discard the whole input, as Python does
+        if interrupted {
+            println!("KeyboardInterrupt");
+            continue;
+        }
 
-        prec.insert('=', 2);
-        prec.insert('<', 10);
-        prec.insert('+', 20);
-        prec.insert('-', 20);
-        prec.insert('*', 40);
-        prec.insert('/', 40);
+        // print the tokens of the input instead of running it
+        if let Some(input) = input.strip_prefix(":tokens") {
+            match dump_tokens(input) {
+                Ok(tokens) => print!("{}", tokens),
+                Err(err) => println!("!> {}", paint_error(EvalError::Parse(err.into()), color)),
+            }
 
-        // Parse and (optionally) display input
-        if display_lexer_output {
-            println!(
-                "-> Attempting to parse lexed input: \n{:?}\n",
-                Lexer::new(input.as_str()).collect::<Vec<Token>>()
-            );
+            continue;
         }
 
-        // make module
-        let module = context.create_module("tmp");
+        // seed the generator of rand, so that its results can be repeated
+        if let Some(seed) = input.strip_prefix(":seed") {
+            match seed.trim().parse() {
+                Ok(seed) => session.seed(seed),
+                Err(_) => println!(
+                    "!> {}",
+                    paint_error("Expected a seed from 0 to 2^64 - 1.", color)
+                ),
+            }
 
-        // recompile every previously parsed function into the new module
-        for prev in &previous_exprs {
-            Compiler::compile(&context, &builder, &module, prev)
-                .expect("Cannot re-add previously compiled function.");
+            continue;
         }
 
-        let (function, is_anonymous) = match Parser::new(input, &mut prec).parse() {
-            Ok(fun) => {
-                let is_anon = fun.is_anon;
+        // print later results in another base
+        if let Some(name) = input.strip_prefix(":base") {
+            if !settings.set_base(name.trim()) {
+                println!(
+                    "!> {}",
+                    paint_error("Expected one of dec, hex or bin.", color)
+                );
+            }
 
-                if display_parser_output {
-                    if is_anon {
-                        println!("-> Expression parsed: \n{:?}\n", fun.body);
-                    } else {
-                        println!("-> Function parsed: \n{:?}\n", fun);
+            continue;
+        }
+
+        // save the last result into a memory slot, from M1 to M9
+        if let Some(slot) = input.strip_prefix(":store") {
+            match slot.trim().parse() {
+                Ok(slot @ 1..=9) => {
+                    if let Err(err) = session.store(slot) {
+                        println!("!> {}", paint_error(err, color));
                     }
                 }
+                _ => println!(
+                    "!> {}",
+                    paint_error("Expected a memory slot from 1 to 9.", color)
+                ),
+            }
+
+            continue;
+        }
 
-                match Compiler::compile(&context, &builder, &module, &fun) {
-                    Ok(function) => {
-                        if !is_anon {
-                            // only add it now to ensure it is correct
-                            previous_exprs.push(fun);
-                        }
+        // list the global variables, with their value in the current base
+        if input.trim() == ":vars" {
+            println!("Variables:");
 
-                        (function, is_anon)
-                    }
-                    Err(err) => {
-                        println!("!> Error compiling function: {}", err);
-                        continue;
-                    }
-                }
+            for (name, value) in session.variables() {
+                println!("  {} = {}", name, settings.base.format(value));
             }
-            Err(err) => {
-                println!("!> Error parsing expression: {}", err);
-                continue;
+
+            continue;
+        }
+
+        // toggle a '%' ending an operand meaning percent, as in '200 * 50%'
+        if input.trim() == ":percent" {
+            settings.percent = !settings.percent;
+            session.percent = settings.percent;
+
+            if settings.percent {
+                println!("A trailing '%' now divides by 100.");
+            } else {
+                println!("A trailing '%' is the modulo operator again.");
+            }
+
+            continue;
+        }
+
+        // print how inputs are grouped before running them, as in '(2 + (3 * 4))'
+        if let Some(state) = input.strip_prefix(":echo") {
+            match state.trim() {
+                "on" => settings.echo = true,
+                "off" => settings.echo = false,
+                _ => println!("!> {}", paint_error("Expected one of on or off.", color)),
             }
-        };
 
-        run_passes_on(&module);
+            continue;
+        }
 
-        if display_compiler_output {
-            println!("-> Expression compiled to IR:");
-            function.print_to_stderr();
+        // print the settings changed by the commands above
+        if input.trim() == ":settings" {
+            print!("{}", settings.describe());
+            continue;
         }
 
-        if is_anonymous {
-            let ee = module
-                .create_jit_execution_engine(OptimizationLevel::None)
-                .unwrap();
+        // reset the named setting, or all of them without a name
+        if let Some(name) = input.strip_prefix(":reset") {
+            if settings.reset(name.trim()) {
+                session.percent = settings.percent;
+            } else {
+                let (last, names) = Settings::NAMES.split_last().unwrap();
+                let message = format!("Expected one of {} or {}.", names.join(", "), last);
+
+                println!("!> {}", paint_error(message, color));
+            }
 
-            let fn_name = function.get_name().to_str().unwrap();
-            let maybe_fn = unsafe { ee.get_function::<unsafe extern "C" fn() -> f64>(fn_name) };
-            let compiled_fn = match maybe_fn {
-                Ok(f) => f,
+            continue;
+        }
+
+        // run an input again and again, as in ':watch 1000 rand(100)'
+        if let Some(rest) = input.strip_prefix(":watch") {
+            let interval = rest
+                .trim_start()
+                .split_once(char::is_whitespace)
+                .and_then(|(ms, input)| Some((ms.parse().ok()?, input)));
+
+            match interval {
+                Some((ms, input)) if ms > 0 => {
+                    let interrupted = watch(
+                        &mut session,
+                        Duration::from_millis(ms),
+                        input,
+                        |result| match result {
+                            Ok(Some(value)) => println!("==> {}", settings.base.format(*value)),
+                            Ok(None) => (),
+                            Err(err) => println!("!> {}", paint_error(err, color)),
+                        },
+                    );
+
+                    if interrupted {
+                        println!("KeyboardInterrupt");
+                    }
+                }
+                _ => println!(
+                    "!> {}",
+                    paint_error("Expected an interval in milliseconds and an input.", color)
+                ),
+            }
+
+            continue;
+        }
+
+        // write the inputs that ran without error, so that running the script replays them
+        if let Some(path) = input.strip_prefix(":save") {
+            let path = path.trim();
+
+            match save_inputs(path, &accepted) {
+                Ok(()) => println!("Saved {} inputs to '{}'.", accepted.len(), path),
                 Err(err) => {
-                    println!("!> Error during execution: {:?}", err);
-                    continue;
+                    let message = format!("Could not save to '{}': {}", path, err);
+
+                    println!("!> {}", paint_error(message, color));
                 }
-            };
+            }
+
+            continue;
+        }
 
-            unsafe {
-                println!("==> {}", compiled_fn.call());
+        // print the IR of the input instead of running it
+        if let Some(input) = input.strip_prefix(":ir") {
+            match session.ir(input) {
+                Ok(ir) => print!("{}", ir),
+                Err(err) => println!("!> {}", paint_error(err, color)),
             }
+
+            continue;
         }
+
+        // time the whole evaluation of the input, parsing and compilation included
+        let (input, timed) = match input.strip_prefix(":time") {
+            Some(input) => (input, true),
+            None => (input.as_str(), false),
+        };
+
+        if settings.echo {
+            for expr in session.echo(input) {
+                println!("{}", expr);
+            }
+        }
+
+        let start = Instant::now();
+        let result = session.run_program(input);
+        let elapsed = start.elapsed();
+
+        if INTERRUPTED.swap(false, Ordering::SeqCst) {
+            println!("KeyboardInterrupt");
+            continue;
+        }
+
+        if result.is_ok() {
+            accepted.push(input.trim().to_string());
+        }
+
+        match result {
+            Ok(Some(value)) => println!("==> {}", settings.base.format(value)),
+            Ok(None) => (),
+            Err(err) => println!("!> {}", paint_error(err, color)),
+        }
+
+        if timed {
+            println!("Wall time: {}", format_duration(elapsed));
+        }
+    }
+
+    if let Some(ref path) = history {
+        if let Err(err) = editor.save_history(path) {
+            eprintln!("Could not save the history: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn help_lists_syntax() {
+        let help = help();
+
+        assert!(help.contains("  + -\n"));
+        assert!(help.contains("( )"));
+        assert!(help.contains(":tokens <input>"));
+        assert!(help.contains(":time <input>"));
+    }
+
+    #[test]
+    fn continuation_prompt_as_wide() {
+        assert_eq!(continuation_prompt(">>>"), "...");
+        assert_eq!(continuation_prompt("calc> "), "..... ");
+        assert_eq!(continuation_prompt(""), "");
+    }
+
+    #[test]
+    fn format_json() {
+        assert_eq!(json_string("a \"b\"\\\n"), "\"a \\\"b\\\"\\\\\\n\"");
+        assert_eq!(json_string("\x07"), "\"\\u0007\"");
+        assert_eq!(
+            json_result(1, &Ok(Some(2.5))),
+            "{\"ok\":true,\"value\":2.5}"
+        );
+        assert_eq!(
+            json_result(1, &Ok(Some(f64::NAN))),
+            "{\"ok\":true,\"value\":\"NaN\"}"
+        );
+        assert_eq!(json_result(1, &Ok(None)), "{\"ok\":true}");
+        assert_eq!(
+            json_result(3, &Err(EvalError::Compile("Undefined variable."))),
+            concat!(
+                "{\"ok\":false,\"error\":\"Error compiling function: Undefined variable.\",",
+                "\"kind\":\"Compile\",\"line\":3}"
+            )
+        );
+    }
+
+    #[test]
+    fn return_lines() {
+        assert!(is_return("return"));
+        assert!(is_return("return 1 + 2"));
+        assert!(is_return("return\t(1 +\n2)"));
+        assert!(!is_return("returned"));
+        assert!(!is_return("return_value = 1"));
+        assert!(!is_return("x = return"));
+    }
+
+    #[test]
+    fn paint_error_in_red() {
+        assert_eq!(paint_error("Oops.", false), "Oops.");
+        assert_eq!(paint_error("Oops.", true), "\x1b[31mOops.\x1b[0m");
+    }
+
+    #[test]
+    fn describe_and_reset_settings() {
+        let mut settings = Settings::default();
+
+        assert_eq!(
+            settings.describe(),
+            "  base = dec\n  percent = off\n  echo = off\n"
+        );
+
+        settings.base = Base::from_name("hex").unwrap();
+        settings.percent = true;
+
+        assert_eq!(
+            settings.describe(),
+            "  base = hex\n  percent = on\n  echo = off\n"
+        );
+        assert!(settings.reset("base"));
+        assert_eq!(
+            settings.describe(),
+            "  base = dec\n  percent = on\n  echo = off\n"
+        );
+        assert!(!settings.reset("width"));
+        assert!(settings.reset(""));
+        assert_eq!(settings, Settings::default());
+
+        for name in Settings::NAMES {
+            assert!(settings.reset(name));
+        }
+    }
+
+    #[test]
+    fn format_in_base() {
+        let base = |name| Base::from_name(name).unwrap();
+
+        assert_eq!(base("hex").format(255.0), "0xff");
+        assert_eq!(base("hex").format(-255.0), "-0xff");
+        assert_eq!(base("bin").format(5.0), "0b101");
+        assert_eq!(base("bin").format(0.0), "0b0");
+        assert_eq!(base("dec").format(255.0), "255");
+        assert_eq!(base("hex").format(2.5), "2.5");
+        assert_eq!(base("hex").format(-9.3e18), "-0x81103cb9fb220000");
+        assert_eq!(base("hex").format(1e19), "0x8ac7230489e80000");
+        assert_eq!(base("hex").format(-1e19), "-0x8ac7230489e80000");
+        assert_eq!(base("hex").format(2e19), "20000000000000000000");
+        assert_eq!(base("bin").format(f64::NAN), "NaN");
+        assert_eq!(Base::from_name("oct"), None);
+    }
+
+    #[test]
+    fn base_command_formats_results() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+        let mut settings = Settings::default();
+        let value = session.run_program("255").unwrap().unwrap();
+
+        assert_eq!(settings.base.format(value), "255");
+        assert!(settings.set_base("hex"));
+        assert_eq!(settings.base.format(value), "0xff");
+        assert!(!settings.set_base("oct"));
+        assert_eq!(settings.base.format(value), "0xff");
+        assert!(settings.set_base("bin"));
+        assert_eq!(settings.base.format(value), "0b11111111");
+    }
+
+    #[test]
+    fn save_inputs_as_script() {
+        let path = env::temp_dir().join("sino_save_inputs_as_script.sn");
+        let inputs = [
+            "def f(a) a * 2".to_string(),
+            "x = f(\n  3)".to_string(),
+            "x + 1".to_string(),
+        ];
+
+        save_inputs(path.to_str().unwrap(), &inputs).unwrap();
+
+        let script = fs::read_to_string(&path).unwrap();
+
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(script, "def f(a) a * 2\nx = f(   3)\nx + 1\n");
+    }
+
+    #[test]
+    fn split_script_lines() {
+        let lines = |source| script_lines(source).collect::<Vec<_>>();
+
+        assert_eq!(lines("1\n2\n"), ["1", "2"]);
+        assert_eq!(lines("1\r\n2\r\n"), ["1", "2"]);
+        assert_eq!(lines("1\r2\r\n3"), ["1", "2", "3"]);
+    }
+
+    #[test]
+    fn watch_until_interrupted() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+        let mut results = Vec::new();
+
+        // interrupt the loop as Ctrl+C would, after a few runs
+        let interrupter = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(100));
+            INTERRUPTED.store(true, Ordering::SeqCst);
+        });
+
+        let interrupted = watch(
+            &mut session,
+            Duration::from_millis(20),
+            "x = 0; x + 1",
+            |result| results.push(result.as_ref().unwrap().unwrap()),
+        );
+
+        interrupter.join().unwrap();
+
+        assert!(interrupted);
+        assert!(results.len() >= 2);
+        assert!(results.iter().all(|&value| value == 1.0));
+        assert!(!INTERRUPTED.load(Ordering::SeqCst));
+
+        let mut errors = 0;
+
+        assert!(!watch(
+            &mut session,
+            Duration::from_millis(20),
+            "unknown()",
+            |result| errors += result.is_err() as usize
+        ));
+        assert_eq!(errors, 1);
+    }
+
+    #[test]
+    fn format_duration_units() {
+        assert_eq!(format_duration(Duration::from_nanos(420)), "420 ns");
+        assert_eq!(format_duration(Duration::from_nanos(1_500)), "1.50 µs");
+        assert_eq!(format_duration(Duration::from_micros(2_346)), "2.35 ms");
+        assert_eq!(format_duration(Duration::from_millis(12_000)), "12.00 s");
     }
 }
@@ -0,0 +1,1722 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use inkwell::builder::Builder;
+use inkwell::context::Context;
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::module::Module;
+use inkwell::values::FunctionValue;
+// #[llvm_versions(4.0..=15.0)]
+// use inkwell::passes::PassManager;
+use inkwell::OptimizationLevel;
+// #[llvm_versions(16.0..=latest)]
+use inkwell::{
+    passes::PassBuilderOptions,
+    targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine},
+};
+
+// use inkwell_internals::llvm_versions;
+
+use crate::implementation_typed_pointers::*;
+use crate::interpreter::Interpreter;
+use crate::lexer::{check_strict, is_blank, split_statements, tokenize};
+
+/// Built-in binary operators along with their precedence, from the loosest to the tightest.
+pub const BINARY_OPERATORS: &[(&str, i32)] = &[
+    ("=", 2),
+    ("or", 3),
+    ("and", 4),
+    ("|", 6),
+    ("^", 7),
+    ("&", 8),
+    ("<", 10),
+    (">", 10),
+    ("<=", 10),
+    (">=", 10),
+    ("==", 10),
+    ("!=", 10),
+    ("<<", 15),
+    (">>", 15),
+    ("+", 20),
+    ("-", 20),
+    ("*", 40),
+    ("/", 40),
+    ("//", 40),
+    ("%", 40),
+    ("**", 60),
+];
+
+/// Default maximum length in bytes of an input, beyond which it is rejected before being
+/// parsed, rather than growing huge token vectors and syntax trees.
+const DEFAULT_MAX_INPUT_LEN: usize = 1 << 20;
+
+/// Name of the global variable holding the value of the last top-level expression.
+/// Assigning to it only evaluates the assigned value, which is discarded.
+pub(crate) const LAST_RESULT: &str = "_";
+
+// ======================================================================================
+// SESSION ==============================================================================
+// ======================================================================================
+
+/// Defines an error encountered while evaluating an input, tagged with the stage that failed.
+#[derive(Debug)]
+pub enum EvalError {
+    Parse(ParseError),
+    Compile(&'static str),
+    Execution(String),
+    Emit(String),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::Parse(err) => write!(f, "Error parsing expression: {}", err),
+            EvalError::Compile(err) => write!(f, "Error compiling function: {}", err),
+            EvalError::Execution(err) => write!(f, "Error during execution: {}", err),
+            EvalError::Emit(err) => write!(f, "Error emitting file: {}", err),
+        }
+    }
+}
+
+impl EvalError {
+    /// Returns the name of the stage that failed, as the variant is named.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EvalError::Parse(_) => "Parse",
+            EvalError::Compile(_) => "Compile",
+            EvalError::Execution(_) => "Execution",
+            EvalError::Emit(_) => "Emit",
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+// #[llvm_versions(4.0..=15.0)]
+// fn run_passes_on(module: &Module) {
+//     let fpm = PassManager::create(());
+
+//     fpm.add_instruction_combining_pass();
+//     fpm.add_reassociate_pass();
+//     fpm.add_gvn_pass();
+//     fpm.add_cfg_simplification_pass();
+//     fpm.add_basic_alias_analysis_pass();
+//     fpm.add_promote_memory_to_register_pass();
+
+//     fpm.run_on(module);
+// }
+
+/// Converts a comparison result to the value it evaluates to in the language.
+pub(crate) fn bool_to_f64(value: bool) -> f64 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Creates a `TargetMachine` for the host, used to run optimization passes.
+fn create_target_machine(level: OptimizationLevel) -> TargetMachine {
+    Target::initialize_all(&InitializationConfig::default());
+    let target_triple = TargetMachine::get_default_triple();
+    let target = Target::from_triple(&target_triple).unwrap();
+
+    target
+        .create_target_machine(
+            &target_triple,
+            "generic",
+            "",
+            level,
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .unwrap()
+}
+
+/// Scrambles the given seed into a state of the random number generator, which is never 0.
+pub(crate) fn random_state(seed: u64) -> u64 {
+    // SplitMix64
+    let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+
+    z.max(1)
+}
+
+/// Returns a pseudo-random integer in `[0, bound)` drawn from the generator whose
+/// state is at `state`, or NaN if `bound` is not positive.
+/// Called by the compiled code of the `rand` built-in function.
+pub(crate) extern "C" fn random_below(state: *mut u64, bound: f64) -> f64 {
+    if bound.is_nan() || bound <= 0.0 {
+        return f64::NAN;
+    }
+
+    // xorshift64*
+    let state = unsafe { &mut *state };
+
+    *state ^= *state >> 12;
+    *state ^= *state << 25;
+    *state ^= *state >> 27;
+
+    let unit = (state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64;
+
+    (unit * bound).floor()
+}
+
+/// Creates the execution engine running the inputs of a session, binding the runtime
+/// of the `rand` built-in function to the given state, or `None` if the JIT is unavailable.
+fn create_engine<'ctx>(
+    context: &'ctx Context,
+    level: OptimizationLevel,
+    random_state: &mut u64,
+) -> Option<ExecutionEngine<'ctx>> {
+    let module = context.create_module("sino");
+    let (state, random) = declare_random(context, &module);
+
+    let engine = module.create_jit_execution_engine(level).ok()?;
+
+    // later modules only declare these, and the engine links them by name
+    engine.add_global_mapping(&state, random_state as *mut u64 as usize);
+    engine.add_global_mapping(&random, random_below as *const () as usize);
+
+    Some(engine)
+}
+
+/// Returns the precedences of the built-in binary operators.
+pub(crate) fn builtin_precedences() -> HashMap<String, i32> {
+    BINARY_OPERATORS
+        .iter()
+        .map(|&(op, prec)| (op.to_string(), prec))
+        .collect()
+}
+
+// #[llvm_versions(16.0..=latest)]
+fn run_passes_on(module: &Module, target_machine: &TargetMachine) {
+    let passes: &[&str] = &[
+        "instcombine",
+        "reassociate",
+        "gvn",
+        "simplifycfg",
+        // "basic-aa",
+        "mem2reg",
+    ];
+
+    module
+        .run_passes(
+            passes.join(",").as_str(),
+            target_machine,
+            PassBuilderOptions::create(),
+        )
+        .unwrap();
+}
+
+/// Defines the state shared by successive inputs: user-defined functions,
+/// operator precedences and global variables.
+///
+/// Every input is compiled into its own module, which is added to a single
+/// execution engine living as long as the session. Functions defined by
+/// earlier inputs are only declared in later modules, and the engine links
+/// calls to them.
+///
+/// When `interpret` is set, inputs are evaluated by a tree-walking interpreter
+/// instead, which is the case by default when the JIT is unavailable.
+/// Setting `trace` also selects the interpreter, which then prints every binary
+/// operation it evaluates. Both are meant to be set before running any input.
+/// Setting `percent` makes a '%' not followed by an operand divide by 100.
+/// Setting `strict` rejects decimal literals with leading zeros and full-width forms.
+/// Inputs longer than `max_input_len` bytes, 1 MiB by default, fail to parse.
+pub struct Session<'ctx> {
+    pub display_lexer_output: bool,
+    pub display_parser_output: bool,
+    pub display_compiler_output: bool,
+    pub interpret: bool,
+    pub trace: bool,
+    pub percent: bool,
+    pub strict: bool,
+    pub max_input_len: usize,
+
+    context: &'ctx Context,
+    builder: Builder<'ctx>,
+    engine: Option<ExecutionEngine<'ctx>>,
+    random_state: Box<u64>,
+    opt_level: OptimizationLevel,
+    target_machine: TargetMachine,
+    prec: HashMap<String, i32>,
+    declarations: Vec<Function>,
+    globals: HashMap<String, Box<f64>>,
+    anon_count: usize,
+}
+
+impl<'ctx> Session<'ctx> {
+    /// Creates a new `Session` compiling into the given `Context`, without optimizing
+    /// the generated machine code.
+    pub fn new(context: &'ctx Context) -> Self {
+        Self::with_optimization_level(context, OptimizationLevel::None)
+    }
+
+    /// Creates a new `Session` compiling into the given `Context`, whose machine code
+    /// is generated with the given optimization level.
+    pub fn with_optimization_level(context: &'ctx Context, level: OptimizationLevel) -> Self {
+        // seed from the clock, so that the results of 'rand' differ between sessions
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        let mut random_state = Box::new(random_state(seed));
+        let engine = create_engine(context, level, random_state.as_mut());
+
+        Session {
+            display_lexer_output: false,
+            display_parser_output: false,
+            display_compiler_output: false,
+            interpret: engine.is_none(),
+            trace: false,
+            percent: false,
+            strict: false,
+            max_input_len: DEFAULT_MAX_INPUT_LEN,
+            context,
+            builder: context.create_builder(),
+            engine,
+            random_state,
+            opt_level: level,
+            target_machine: create_target_machine(level),
+            prec: builtin_precedences(),
+            declarations: Vec::new(),
+            globals: HashMap::new(),
+            anon_count: 0,
+        }
+    }
+
+    /// Forgets every variable, function and operator defined so far, along with
+    /// the last result, as if the session had just been created.
+    pub fn clear(&mut self) {
+        // compiled code may refer to the storage of globals, so it is freed first,
+        // along with the engine
+        self.engine = create_engine(self.context, self.opt_level, self.random_state.as_mut());
+        self.globals.clear();
+        self.declarations.clear();
+        self.prec = builtin_precedences();
+        self.anon_count = 0;
+    }
+
+    /// Returns the top-level expressions of the given input as `pretty` renders them,
+    /// leaving out definitions and statements that fail to parse, without defining
+    /// anything. Operators defined by earlier statements apply to later ones.
+    pub fn echo(&self, input: &str) -> Vec<String> {
+        let mut prec = self.prec.clone();
+
+        split_statements(input)
+            .into_iter()
+            .filter(|(_, statement)| !is_blank(statement))
+            .filter_map(|(_, statement)| {
+                let mut parser = Parser::new(statement.to_string(), &mut prec);
+
+                if self.percent {
+                    parser = parser.with_percent();
+                }
+
+                match parser.parse() {
+                    Ok(Function {
+                        body: Some(body),
+                        is_anon: true,
+                        ..
+                    }) => Some(pretty(&body)),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the global variables defined so far along with their value, sorted by name,
+    /// leaving out the last result.
+    pub fn variables(&self) -> Vec<(&str, f64)> {
+        let mut variables: Vec<_> = self
+            .globals
+            .iter()
+            .filter(|(name, _)| name.as_str() != LAST_RESULT)
+            .map(|(name, value)| (name.as_str(), **value))
+            .collect();
+
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+
+    /// Seeds the random number generator of the `rand` built-in function,
+    /// which then returns the same sequence of numbers for the same seed.
+    pub fn seed(&mut self, seed: u64) {
+        *self.random_state = random_state(seed);
+    }
+
+    /// Saves the last result into the memory slot `slot`, from 1 to 9, which later inputs
+    /// read as the global variable `M1` to `M9`. Fails if no expression was run yet.
+    pub fn store(&mut self, slot: u8) -> Result<(), EvalError> {
+        if !self.globals.contains_key(LAST_RESULT) {
+            return Err(EvalError::Execution(
+                "There is no result to store yet.".to_string(),
+            ));
+        }
+
+        // assign it as an input would, so that either backend binds the new global
+        self.run(&format!("M{} = {}", slot, LAST_RESULT))
+            .map(|_| ())
+    }
+
+    /// Parses the given input, giving a name of its own to a top-level expression.
+    fn parse(&mut self, input: &str) -> Result<Function, EvalError> {
+        // Parse and (optionally) display input
+        if self.display_lexer_output {
+            println!(
+                "-> Attempting to parse lexed input: \n{:?}\n",
+                tokenize(input)
+            );
+        }
+
+        if self.strict {
+            check_strict(input).map_err(|err| EvalError::Parse(err.into()))?;
+        }
+
+        let mut parser = Parser::new(input.to_string(), &mut self.prec);
+
+        if self.percent {
+            parser = parser.with_percent();
+        }
+
+        let mut fun = parser.parse().map_err(EvalError::Parse)?;
+
+        if fun.is_anon {
+            // the engine keeps the symbols of previous top-level expressions,
+            // so each of them needs a name of its own
+            fun.prototype.name = format!("{}{}", fun.prototype.name, self.anon_count);
+            self.anon_count += 1;
+        }
+
+        if self.display_parser_output {
+            if fun.is_anon {
+                println!("-> Expression parsed: \n{:?}\n", fun.body);
+            } else {
+                println!("-> Function parsed: \n{:?}\n", fun);
+            }
+        }
+
+        Ok(fun)
+    }
+
+    /// Compiles the given function into a module of its own, in which every
+    /// previously defined function is declared, then runs the optimization passes on it.
+    fn build_module(
+        &self,
+        fun: &Function,
+    ) -> Result<(Module<'ctx>, FunctionValue<'ctx>), EvalError> {
+        // make module, declaring every previously defined function in it
+        let module = self.context.create_module("tmp");
+
+        for decl in &self.declarations {
+            Compiler::compile(self.context, &self.builder, &module, decl, &self.globals)
+                .expect("Cannot re-declare previously compiled function.");
+        }
+
+        let function = Compiler::compile(self.context, &self.builder, &module, fun, &self.globals)
+            .map_err(EvalError::Compile)?;
+
+        run_passes_on(&module, &self.target_machine);
+
+        Ok((module, function))
+    }
+
+    /// Evaluates the given expression without compiling it, as long as it only involves
+    /// literals and built-in operators. Returns `None` when it needs to be compiled.
+    fn try_const_eval(&self, expr: &Expr) -> Option<f64> {
+        match *expr {
+            Expr::Number(nb) => Some(nb),
+            Expr::Variable(ref name) => builtin_constant(name),
+
+            Expr::Call {
+                ref fn_name,
+                ref args,
+            } => {
+                // user-defined unary operators take precedence over the built-in ones
+                if self
+                    .declarations
+                    .iter()
+                    .any(|decl| decl.prototype.name == *fn_name)
+                {
+                    return None;
+                }
+
+                match (fn_name.as_str(), args.as_slice()) {
+                    ("unary-", [operand]) => Some(-self.try_const_eval(operand)?),
+                    ("unary+", [operand]) => self.try_const_eval(operand),
+
+                    _ => None,
+                }
+            }
+
+            Expr::Binary {
+                ref op,
+                ref left,
+                ref right,
+            } => {
+                let lhs = self.try_const_eval(left)?;
+                let rhs = self.try_const_eval(right)?;
+
+                // the relational operators compile to unordered comparisons
+                let unordered = lhs.is_nan() || rhs.is_nan();
+
+                match op.as_str() {
+                    "+" => Some(lhs + rhs),
+                    "-" => Some(lhs - rhs),
+                    "*" => Some(lhs * rhs),
+                    "/" => Some(lhs / rhs),
+                    "//" => Some((lhs / rhs).floor()),
+                    "%" if rhs != 0.0 => Some(lhs % rhs),
+                    "**" => Some(lhs.powf(rhs)),
+
+                    "<" => Some(bool_to_f64(lhs < rhs || unordered)),
+                    ">" => Some(bool_to_f64(lhs > rhs || unordered)),
+                    "<=" => Some(bool_to_f64(lhs <= rhs || unordered)),
+                    ">=" => Some(bool_to_f64(lhs >= rhs || unordered)),
+                    "==" => Some(bool_to_f64(lhs == rhs)),
+                    "!=" => Some(bool_to_f64(lhs != rhs)),
+
+                    "&" | "|" | "^" | "<<" | ">>" => {
+                        // leave the compiler to report shift counts out of range
+                        if (op == "<<" || op == ">>") && !is_shift_count(rhs) {
+                            return None;
+                        }
+
+                        // out-of-range operands saturate as in the compiled code, NaN giving 0
+                        let (lhs, rhs) = (lhs as i64, rhs as i64);
+
+                        let result = match op.as_str() {
+                            "&" => lhs & rhs,
+                            "|" => lhs | rhs,
+                            "^" => lhs ^ rhs,
+                            "<<" => lhs << rhs,
+                            _ => lhs >> rhs,
+                        };
+
+                        Some(result as f64)
+                    }
+
+                    _ => None,
+                }
+            }
+
+            _ => None,
+        }
+    }
+
+    /// Parses and compiles the given input without running it, and returns the LLVM IR
+    /// of the resulting module.
+    pub fn ir(&mut self, input: &str) -> Result<String, EvalError> {
+        let fun = self.parse(input)?;
+        let (module, _) = self.build_module(&fun)?;
+
+        Ok(module.print_to_string().to_string())
+    }
+
+    /// Parses and compiles the given input without running it, into a module targeting the host.
+    fn build_host_module(&mut self, input: &str) -> Result<Module<'ctx>, EvalError> {
+        let fun = self.parse(input)?;
+        let (module, _) = self.build_module(&fun)?;
+
+        module.set_triple(&self.target_machine.get_triple());
+        module.set_data_layout(&self.target_machine.get_target_data().get_data_layout());
+
+        Ok(module)
+    }
+
+    /// Parses and compiles the given input without running it, and writes the resulting
+    /// module to an object file for the host at `path`.
+    pub fn emit_object(&mut self, input: &str, path: &Path) -> Result<(), EvalError> {
+        let module = self.build_host_module(input)?;
+
+        self.target_machine
+            .write_to_file(&module, FileType::Object, path)
+            .map_err(|err| EvalError::Emit(err.to_string()))
+    }
+
+    /// Parses and compiles the given input without running it, and writes the resulting
+    /// module as textual LLVM IR at `path`.
+    pub fn emit_llvm(&mut self, input: &str, path: &Path) -> Result<(), EvalError> {
+        let module = self.build_host_module(input)?;
+
+        module
+            .print_to_file(path)
+            .map_err(|err| EvalError::Emit(err.to_string()))
+    }
+
+    /// Parses and compiles the given input, then runs it if it is a top-level expression.
+    /// Returns the value of the expression, or `None` if the input was a definition
+    /// or an assignment, whose value is not kept as the last result.
+    ///
+    /// Top-level expressions made of literals and built-in operators only are
+    /// evaluated directly, without going through LLVM.
+    pub fn run(&mut self, input: &str) -> Result<Option<f64>, EvalError> {
+        if input.len() > self.max_input_len {
+            // report the first character past the limit
+            let (index, (offset, c)) = input
+                .char_indices()
+                .enumerate()
+                .find(|(_, (offset, c))| offset + c.len_utf8() > self.max_input_len)
+                .unwrap();
+
+            return Err(EvalError::Parse(ParseError {
+                error: "Input too large.",
+                index,
+                span: offset..offset + c.len_utf8(),
+            }));
+        }
+
+        let fun = self.parse(input)?;
+
+        if self.interpret || self.trace {
+            return self.evaluate(fun);
+        }
+
+        // the storage of the last result is only bound once a compiled expression declared it
+        if fun.is_anon && self.globals.contains_key(LAST_RESULT) && !self.display_compiler_output {
+            if let Some(value) = self.try_const_eval(fun.body.as_ref().unwrap()) {
+                *self.globals.get_mut(LAST_RESULT).unwrap().as_mut() = value;
+
+                return Ok(Some(value));
+            }
+        }
+
+        self.execute(fun)
+    }
+
+    /// Runs each of the statements of the given input, separated by ';', in order.
+    /// Returns the value of the last statement as `run` does, or `None` if it is blank,
+    /// so that a trailing ';' discards the value of an expression.
+    pub fn run_program(&mut self, input: &str) -> Result<Option<f64>, EvalError> {
+        let mut result = None;
+
+        for (start, statement) in split_statements(input) {
+            let offset = input
+                .char_indices()
+                .nth(start)
+                .map_or(0, |(offset, _)| offset);
+
+            if is_blank(statement) {
+                result = None;
+                continue;
+            }
+
+            result = self.run(statement).map_err(|err| match err {
+                // report columns within the whole input
+                EvalError::Parse(err) => EvalError::Parse(ParseError {
+                    error: err.error,
+                    index: start + err.index,
+                    span: offset + err.span.start..offset + err.span.end,
+                }),
+                err => err,
+            })?;
+        }
+
+        Ok(result)
+    }
+
+    /// Evaluates the given function with the interpreter if it is a top-level expression,
+    /// or keeps it to be called by later inputs otherwise.
+    fn evaluate(&mut self, fun: Function) -> Result<Option<f64>, EvalError> {
+        if !fun.is_anon {
+            // the interpreter needs the whole definition, unlike the engine
+            self.declarations.push(fun);
+
+            return Ok(None);
+        }
+
+        let body = fun.body.as_ref().unwrap();
+        let is_assignment = matches!(*body, Expr::Binary { ref op, .. } if op == "=");
+        let mut interpreter = Interpreter::new(
+            &self.declarations,
+            &mut self.globals,
+            self.random_state.as_mut(),
+        );
+
+        if self.trace {
+            interpreter = interpreter.with_trace();
+        }
+
+        let value = interpreter.eval(body);
+
+        for step in interpreter.into_steps() {
+            println!("{}", step);
+        }
+
+        let value = value?;
+
+        if is_assignment {
+            return Ok(None);
+        }
+
+        **self
+            .globals
+            .entry(LAST_RESULT.to_string())
+            .or_insert_with(|| Box::new(0.0)) = value;
+
+        Ok(Some(value))
+    }
+
+    /// Compiles the given function, then runs it if it is a top-level expression.
+    fn execute(&mut self, fun: Function) -> Result<Option<f64>, EvalError> {
+        let engine = self.engine.as_ref().ok_or_else(|| {
+            EvalError::Execution("The execution engine is unavailable.".to_string())
+        })?;
+        let (module, function) = self.build_module(&fun)?;
+        let is_anon = fun.is_anon;
+        let is_assignment = matches!(fun.body, Some(Expr::Binary { ref op, .. }) if op == "=");
+
+        if self.display_compiler_output {
+            println!("-> Expression compiled to IR:");
+            function.print_to_stderr();
+        }
+
+        if is_anon && module.get_global(LAST_RESULT).is_none() {
+            // declare the last result, so that its storage is bound along with other globals
+            module.add_global(self.context.f64_type(), None, LAST_RESULT);
+        }
+
+        engine.add_module(&module).map_err(|()| {
+            EvalError::Execution("Could not add module to the execution engine.".to_string())
+        })?;
+
+        if !is_anon {
+            // only declare it now to ensure it is correct
+            self.declarations.push(Function {
+                prototype: fun.prototype,
+                body: None,
+                is_anon: false,
+            });
+
+            return Ok(None);
+        }
+
+        // bind new global variables to storage that outlives the module
+        for global in module.get_globals() {
+            let name = global.get_name().to_str().unwrap().to_string();
+
+            if name == RANDOM_STATE_NAME {
+                continue;
+            }
+
+            if let Entry::Vacant(entry) = self.globals.entry(name) {
+                let value = entry.insert(Box::new(0.0));
+
+                engine.add_global_mapping(&global, value.as_mut() as *mut f64 as usize);
+            }
+        }
+
+        let fn_name = function.get_name().to_str().unwrap();
+        let result = unsafe { engine.get_function::<unsafe extern "C" fn() -> f64>(fn_name) }
+            .map(|compiled_fn| unsafe { compiled_fn.call() })
+            .map_err(|err| EvalError::Execution(format!("{:?}", err)));
+
+        // top-level expressions are never called again
+        engine.remove_module(&module).unwrap();
+
+        let value = result?;
+
+        if is_assignment {
+            return Ok(None);
+        }
+
+        *self.globals.get_mut(LAST_RESULT).unwrap().as_mut() = value;
+
+        Ok(Some(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::interpret;
+
+    fn eval_all(inputs: &[&str]) -> Result<Option<f64>, EvalError> {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+        let mut result = Ok(None);
+
+        for input in inputs {
+            result = session.run(input);
+        }
+
+        result
+    }
+
+    #[test]
+    fn run_builtin_operators() {
+        assert_eq!(eval_all(&["17 % 5"]).unwrap(), Some(2.0));
+        assert_eq!(eval_all(&["007 == 7 and 08 == 8"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["2 ** 3 ** 2"]).unwrap(), Some(512.0));
+        assert_eq!(eval_all(&["5 - -3"]).unwrap(), Some(8.0));
+        assert_eq!(eval_all(&["-(2 + 3)"]).unwrap(), Some(-5.0));
+    }
+
+    #[test]
+    fn run_unary_operators() {
+        assert_eq!(eval_all(&["-5"]).unwrap(), Some(-5.0));
+        assert_eq!(eval_all(&["3 * -2"]).unwrap(), Some(-6.0));
+        assert_eq!(eval_all(&["--4"]).unwrap(), Some(4.0));
+        assert_eq!(eval_all(&["+4 - +1"]).unwrap(), Some(3.0));
+        assert_eq!(eval_all(&["5 - -3 == 8"]).unwrap(), Some(1.0));
+    }
+
+    #[test]
+    fn run_fractional_powers() {
+        let sqrt2 = eval_all(&["2.0 ** 0.5"]).unwrap().unwrap();
+
+        assert!((sqrt2 - std::f64::consts::SQRT_2).abs() < 1e-12);
+        assert_eq!(eval_all(&["2 ** 3"]).unwrap(), Some(8.0));
+        assert_eq!(eval_all(&["4 ** -0.5"]).unwrap(), Some(0.5));
+        assert_eq!(
+            eval_all(&["2 ** 0.5 ** 2 == 2 ** 0.25"]).unwrap(),
+            Some(1.0)
+        );
+    }
+
+    #[test]
+    fn run_block_comments() {
+        assert_eq!(eval_all(&["1 /* x */ + 2"]).unwrap(), Some(3.0));
+        assert_eq!(eval_all(&["6 /* / 0 */ / 2"]).unwrap(), Some(3.0));
+        assert!(matches!(eval_all(&["1 /* x"]), Err(EvalError::Parse(_))));
+    }
+
+    #[test]
+    fn run_floor_division() {
+        assert_eq!(eval_all(&["7 // 2"]).unwrap(), Some(3.0));
+        assert_eq!(eval_all(&["-7 // 2"]).unwrap(), Some(-4.0));
+        assert_eq!(eval_all(&["7 // -2"]).unwrap(), Some(-4.0));
+        assert_eq!(eval_all(&["-7 // -2"]).unwrap(), Some(3.0));
+        assert_eq!(eval_all(&["-7 / 2"]).unwrap(), Some(-3.5));
+        assert_eq!(eval_all(&["1 + 9 // 2 * 2"]).unwrap(), Some(9.0));
+        assert_eq!(
+            eval_all(&["def f(a, b) a // b", "f(-7, 2)"]).unwrap(),
+            Some(-4.0)
+        );
+    }
+
+    #[test]
+    fn run_bitwise_operators() {
+        assert_eq!(eval_all(&["6 & 3"]).unwrap(), Some(2.0));
+        assert_eq!(eval_all(&["5 | 2"]).unwrap(), Some(7.0));
+        assert_eq!(eval_all(&["5 ^ 1"]).unwrap(), Some(4.0));
+        assert_eq!(eval_all(&["1 << 4"]).unwrap(), Some(16.0));
+        assert_eq!(eval_all(&["256 >> 2"]).unwrap(), Some(64.0));
+        assert_eq!(eval_all(&["1 | 2 & 3"]).unwrap(), Some(3.0));
+        assert_eq!(eval_all(&["1 + 1 << 2"]).unwrap(), Some(8.0));
+        assert_eq!(eval_all(&["1 << 63"]).unwrap(), Some(-(2f64.powi(63))));
+        assert_eq!(eval_all(&["-8 >> 0.5"]).unwrap(), Some(-8.0));
+    }
+
+    #[test]
+    fn run_bitwise_operators_out_of_range() {
+        // operands saturate to the 64-bit range, NaN truncating to 0
+        assert_eq!(eval_all(&["1e19 & 1"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["-1e19 | 0"]).unwrap(), Some(-(2f64.powi(63))));
+        assert_eq!(eval_all(&["(0 / 0) | 0"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["x = 1e300", "x >> 62"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["x = 0 / 0", "x ^ 5"]).unwrap(), Some(5.0));
+        assert_eq!(
+            eval_all(&["wadd(9223372036854775807, 1)"]).unwrap(),
+            Some(-(2f64.powi(63)))
+        );
+    }
+
+    #[test]
+    fn run_shift_count_checks() {
+        for input in ["1 << -1", "1 << 64", "8 >> (0 - 70)"] {
+            assert!(matches!(eval_all(&[input]), Err(EvalError::Compile(_))));
+        }
+
+        assert!(eval_all(&["x = 64", "1 << x"]).unwrap().unwrap().is_nan());
+        assert!(eval_all(&["x = -1", "8 >> x"]).unwrap().unwrap().is_nan());
+        assert!(eval_all(&["x = 0 / 0", "8 >> x"])
+            .unwrap()
+            .unwrap()
+            .is_nan());
+        assert_eq!(eval_all(&["x = 3", "1 << x"]).unwrap(), Some(8.0));
+    }
+
+    #[test]
+    fn run_builtin_constants() {
+        let pi = eval_all(&["pi"]).unwrap().unwrap();
+        let e = eval_all(&["e"]).unwrap().unwrap();
+
+        assert!((pi - 3.14159265).abs() < 1e-8);
+        assert!((e - 2.71828182).abs() < 1e-8);
+        assert_eq!(eval_all(&["pi * 2"]).unwrap(), Some(std::f64::consts::TAU));
+        assert_eq!(eval_all(&["x = e", "x - e"]).unwrap(), Some(0.0));
+
+        // local variables shadow them, global ones may not
+        assert_eq!(eval_all(&["def f(e) e * 2", "f(3)"]).unwrap(), Some(6.0));
+        assert_eq!(eval_all(&["var pi = 3 in pi + 1"]).unwrap(), Some(4.0));
+        assert_eq!(
+            eval_all(&["for e = 0, e < 3 in 0", "e"]).unwrap(),
+            Some(std::f64::consts::E)
+        );
+        assert!(matches!(
+            eval_all(&["pi = 3"]),
+            Err(EvalError::Compile("Cannot assign to a built-in constant."))
+        ));
+    }
+
+    #[test]
+    fn run_rounding_functions() {
+        assert_eq!(eval_all(&["round(2.6)"]).unwrap(), Some(3.0));
+        assert_eq!(eval_all(&["round(2.5)"]).unwrap(), Some(3.0));
+        assert_eq!(eval_all(&["round(-2.5)"]).unwrap(), Some(-3.0));
+        assert_eq!(eval_all(&["floor(2.9)"]).unwrap(), Some(2.0));
+        assert_eq!(eval_all(&["floor(-2.1) == -3"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["ceil(2.1)"]).unwrap(), Some(3.0));
+        assert_eq!(eval_all(&["ceil(-2.9)"]).unwrap(), Some(-2.0));
+        assert_eq!(
+            eval_all(&["x = 7.5", "floor(x) + ceil(x)"]).unwrap(),
+            Some(15.0)
+        );
+        assert!(eval_all(&["floor(0 / 0)"]).unwrap().unwrap().is_nan());
+        assert!(matches!(
+            eval_all(&["round(1, 2)"]),
+            Err(EvalError::Compile(_))
+        ));
+    }
+
+    #[test]
+    fn run_builtin_functions() {
+        assert_eq!(eval_all(&["abs(-5)"]).unwrap(), Some(5.0));
+        assert_eq!(eval_all(&["abs(2.5)"]).unwrap(), Some(2.5));
+        assert_eq!(eval_all(&["max(3, 7)"]).unwrap(), Some(7.0));
+        assert_eq!(eval_all(&["min(3, 7)"]).unwrap(), Some(3.0));
+        assert_eq!(eval_all(&["max(abs(-9), 4)"]).unwrap(), Some(9.0));
+        assert_eq!(eval_all(&["min(max(1, 2), -abs(3))"]).unwrap(), Some(-3.0));
+        assert_eq!(eval_all(&["def abs(a) 1", "abs(-5)"]).unwrap(), Some(1.0));
+        assert!(matches!(
+            eval_all(&["abs(1, 2)"]),
+            Err(EvalError::Compile(_))
+        ));
+        assert!(matches!(eval_all(&["max(1)"]), Err(EvalError::Compile(_))));
+        assert!(matches!(eval_all(&["foo(1)"]), Err(EvalError::Compile(_))));
+    }
+
+    #[test]
+    fn run_variadic_max_and_min() {
+        assert_eq!(eval_all(&["max(1, 5, 3)"]).unwrap(), Some(5.0));
+        assert_eq!(eval_all(&["max(1, 5, 3, 9)"]).unwrap(), Some(9.0));
+        assert_eq!(eval_all(&["min(4, -2, 8, 0)"]).unwrap(), Some(-2.0));
+        assert_eq!(
+            eval_all(&["x = 7", "min(x, x * 2, x - 1)"]).unwrap(),
+            Some(6.0)
+        );
+
+        for input in ["max()", "min()", "min(1)"] {
+            assert!(matches!(eval_all(&[input]), Err(EvalError::Compile(_))));
+        }
+    }
+
+    #[test]
+    fn run_full_width_forms() {
+        assert_eq!(eval_all(&["（１＋２）＊３"]).unwrap(), Some(9.0));
+        assert!(matches!(eval_all(&["1 ＋ 三"]), Err(EvalError::Parse(_))));
+    }
+
+    #[test]
+    fn run_inline_comment() {
+        assert_eq!(eval_all(&["5 + 3 # this is ignored"]).unwrap(), Some(8.0));
+        assert_eq!(
+            eval_all(&["def f(a) a * 2 # doubles", "f(2) # four"]).unwrap(),
+            Some(4.0)
+        );
+    }
+
+    #[test]
+    fn run_number_theory_functions() {
+        assert_eq!(eval_all(&["isqrt(16)"]).unwrap(), Some(4.0));
+        assert_eq!(eval_all(&["isqrt(17)"]).unwrap(), Some(4.0));
+        assert_eq!(eval_all(&["isqrt(0)"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["isqrt(99_980_001)"]).unwrap(), Some(9999.0));
+        assert_eq!(
+            eval_all(&["isqrt(4503599761588224)"]).unwrap(),
+            Some(67108864.0)
+        );
+        assert_eq!(
+            eval_all(&["isqrt(4503599761588225)"]).unwrap(),
+            Some(67108865.0)
+        );
+        assert_eq!(
+            eval_all(&["x = 4503599761588224", "isqrt(x)"]).unwrap(),
+            Some(67108864.0)
+        );
+        assert!(matches!(
+            eval_all(&["isqrt(-4)"]),
+            Err(EvalError::Compile(_))
+        ));
+        assert!(eval_all(&["def f(a) isqrt(a)", "f(-4)"])
+            .unwrap()
+            .unwrap()
+            .is_nan());
+
+        assert_eq!(eval_all(&["gcd(12, 18)"]).unwrap(), Some(6.0));
+        assert_eq!(eval_all(&["gcd(0, 5) == 5"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["gcd(5, 0)"]).unwrap(), Some(5.0));
+        assert_eq!(eval_all(&["gcd(-12, 8)"]).unwrap(), Some(4.0));
+        assert_eq!(eval_all(&["gcd(17, 5)"]).unwrap(), Some(1.0));
+        assert!(matches!(eval_all(&["gcd(1)"]), Err(EvalError::Compile(_))));
+    }
+
+    #[test]
+    fn run_random() {
+        fn draw(session: &mut Session) -> Vec<f64> {
+            (0..20)
+                .map(|_| session.run("rand(100)").unwrap().unwrap())
+                .collect()
+        }
+
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        session.seed(42);
+        let first = draw(&mut session);
+
+        session.seed(42);
+        assert_eq!(draw(&mut session), first);
+
+        session.seed(43);
+        assert_ne!(draw(&mut session), first);
+
+        assert!(first
+            .iter()
+            .all(|&value| (0.0..100.0).contains(&value) && value.fract() == 0.0));
+
+        // the generator is shared by functions, and survives clearing the session
+        session.run("def roll() rand(6) + 1").unwrap();
+        session.seed(7);
+        let roll = session.run("roll()").unwrap();
+        session.clear();
+        session.seed(7);
+        assert_eq!(session.run("rand(6) + 1").unwrap(), roll);
+
+        assert!(session.run("rand(0)").unwrap().unwrap().is_nan());
+    }
+
+    #[test]
+    fn run_wrapping_functions() {
+        assert_eq!(eval_all(&["wadd(2, 3)"]).unwrap(), Some(5.0));
+        assert_eq!(eval_all(&["wsub(2.9, 3.9)"]).unwrap(), Some(-1.0));
+        assert_eq!(eval_all(&["wmul(-4, 5)"]).unwrap(), Some(-20.0));
+        assert_eq!(
+            eval_all(&["wadd(2 ** 62, 2 ** 62) == -(2 ** 63)"]).unwrap(),
+            Some(1.0)
+        );
+        assert_eq!(
+            eval_all(&["wsub(-(2 ** 63), 1) == 2 ** 63"]).unwrap(),
+            Some(1.0)
+        );
+        assert_eq!(eval_all(&["wmul(2 ** 32, 2 ** 32)"]).unwrap(), Some(0.0));
+        assert!(matches!(eval_all(&["wadd(1)"]), Err(EvalError::Compile(_))));
+    }
+
+    #[test]
+    fn run_factorial() {
+        assert_eq!(eval_all(&["5!"]).unwrap(), Some(120.0));
+        assert_eq!(eval_all(&["0! == 1"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["3! + 1"]).unwrap(), Some(7.0));
+        assert_eq!(eval_all(&["(2 + 1)!"]).unwrap(), Some(6.0));
+        assert_eq!(eval_all(&["-3!"]).unwrap(), Some(-6.0));
+        assert_eq!(eval_all(&["3!!"]).unwrap(), Some(720.0));
+        assert!(matches!(eval_all(&["(-3)!"]), Err(EvalError::Compile(_))));
+        assert!(matches!(eval_all(&["2.5!"]), Err(EvalError::Compile(_))));
+
+        let def = "def f(a) a!";
+
+        assert_eq!(eval_all(&[def, "f(4)"]).unwrap(), Some(24.0));
+        assert_eq!(eval_all(&[def, "f(171)"]).unwrap(), Some(f64::INFINITY));
+        assert!(eval_all(&[def, "f(-1)"]).unwrap().unwrap().is_nan());
+        assert!(eval_all(&[def, "f(2.5)"]).unwrap().unwrap().is_nan());
+    }
+
+    #[test]
+    fn run_comparison_operators() {
+        assert_eq!(eval_all(&["3 < 5"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["5 < 3"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["7 > 9"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["4 <= 4"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["5 <= 4"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["7 >= 9"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["9 >= 9"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["4 == 4"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["4 == 5"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["4 != 5"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["4 != 4"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["(2 < 3) + 1 == 2"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["1 + 2 > 2"]).unwrap(), Some(1.0));
+    }
+
+    #[test]
+    fn run_postfix_conditional() {
+        assert_eq!(eval_all(&["10 if 3 > 2 else 20"]).unwrap(), Some(10.0));
+        assert_eq!(eval_all(&["10 if 3 < 2 else 20"]).unwrap(), Some(20.0));
+        assert_eq!(eval_all(&["1 if 0 else 2 if 1 else 3"]).unwrap(), Some(2.0));
+        assert_eq!(eval_all(&["1 if 0 else 2 if 0 else 3"]).unwrap(), Some(3.0));
+
+        let def = "def f(a) 1 if a > 0 else 2 if a == 0 else 3";
+
+        assert_eq!(eval_all(&[def, "f(5)"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&[def, "f(0)"]).unwrap(), Some(2.0));
+        assert_eq!(eval_all(&[def, "f(-5)"]).unwrap(), Some(3.0));
+    }
+
+    #[test]
+    fn run_chained_comparisons() {
+        assert_eq!(eval_all(&["1 < 2 < 3"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["3 < 2 < 1"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["1 < 2 < 3 < 4"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["1 < 3 < 2 < 4"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["1 < 2 > 0 != 5"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["(3 < 2) < 1"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["x = 5", "1 < x <= 10"]).unwrap(), Some(1.0));
+        assert_eq!(
+            eval_all(&["def f(a) 0 <= a < 10", "f(10)"]).unwrap(),
+            Some(0.0)
+        );
+
+        // the middle operand is evaluated once, and the last one only if needed
+        assert_eq!(
+            eval_all(&[
+                "x = 0",
+                "y = 0",
+                "0 < (x = x + 1) < 1 < (y = 1)",
+                "x * 10 + y"
+            ])
+            .unwrap(),
+            Some(10.0)
+        );
+    }
+
+    #[test]
+    fn run_logical_operators() {
+        assert_eq!(eval_all(&["0 and 5 == 0"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["3 or 0 == 1"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["not 0 == 1"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["not 3"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["2 and 3"]).unwrap(), Some(1.0));
+        assert_eq!(eval_all(&["0 or 0"]).unwrap(), Some(0.0));
+        assert_eq!(eval_all(&["1 or 0 and 0"]).unwrap(), Some(1.0));
+    }
+
+    #[test]
+    fn run_logical_operators_short_circuit() {
+        assert_eq!(
+            eval_all(&["x = 0", "0 and (x = 1)", "x"]).unwrap(),
+            Some(0.0)
+        );
+        assert_eq!(
+            eval_all(&["x = 0", "1 or (x = 1)", "x"]).unwrap(),
+            Some(0.0)
+        );
+        assert_eq!(
+            eval_all(&["x = 0", "1 and (x = 2)", "x"]).unwrap(),
+            Some(2.0)
+        );
+        assert_eq!(
+            eval_all(&["x = 0", "0 or (x = 3)", "x"]).unwrap(),
+            Some(3.0)
+        );
+    }
+
+    #[test]
+    fn run_signed_division() {
+        assert_eq!(eval_all(&["-6 / 2"]).unwrap(), Some(-3.0));
+        assert_eq!(eval_all(&["7 / -2"]).unwrap(), Some(-3.5));
+        assert_eq!(eval_all(&["-7 % 2"]).unwrap(), Some(-1.0));
+        assert_eq!(eval_all(&["7 % -2"]).unwrap(), Some(1.0));
+    }
+
+    #[test]
+    fn run_operator_as_operand() {
+        for input in ["2 + * 3", "* 5", "/ 3", "% 2", "** 2"] {
+            assert!(matches!(
+                eval_all(&[input]),
+                Err(EvalError::Compile(
+                    "Expected an operand before binary operator."
+                ))
+            ));
+        }
+
+        assert!(matches!(
+            eval_all(&["def binary @ 5 (a, b) a", "@ 1"]),
+            Err(EvalError::Compile("Undefined unary operator."))
+        ));
+
+        assert_eq!(
+            eval_all(&["def unary*(a) a * a", "2 + * 3"]).unwrap(),
+            Some(11.0)
+        );
+    }
+
+    #[test]
+    fn run_modulo_by_zero() {
+        assert!(matches!(eval_all(&["10 % 0"]), Err(EvalError::Compile(_))));
+    }
+
+    #[test]
+    fn echo_grouped_expressions() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        assert_eq!(session.echo("2+3*4"), ["(2 + (3 * 4))"]);
+        assert_eq!(
+            session.echo("def binary~ 5 (a, b) a - b; 1 ~ 2 * 3; 1 +; x = -1 < 2"),
+            ["(1 ~ (2 * 3))", "(x = ((-1) < 2))"]
+        );
+        // nothing was defined
+        assert!(session.run("1 ~ 2").is_err());
+
+        session.percent = true;
+
+        assert_eq!(session.echo("200 * 50%"), ["(200 * (50 / 100))"]);
+    }
+
+    #[test]
+    fn list_variables() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        assert!(session.variables().is_empty());
+
+        session.run_program("y = 2; x = 1.5; x + y").unwrap();
+
+        assert_eq!(session.variables(), [("x", 1.5), ("y", 2.0)]);
+
+        session.run("a = y * 2").unwrap();
+
+        assert_eq!(session.variables(), [("a", 4.0), ("x", 1.5), ("y", 2.0)]);
+    }
+
+    #[test]
+    fn store_memory_slots() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        assert!(matches!(session.store(1), Err(EvalError::Execution(_))));
+
+        session.run("6 * 7").unwrap();
+        session.store(1).unwrap();
+        session.run("1").unwrap();
+        session.store(2).unwrap();
+
+        assert_eq!(session.run("M1 + M2").unwrap(), Some(43.0));
+        assert_eq!(session.run("M1 * 2").unwrap(), Some(84.0));
+
+        session.store(1).unwrap();
+
+        assert_eq!(session.run("M1 - M2").unwrap(), Some(83.0));
+        assert!(matches!(session.run("M3"), Err(EvalError::Compile(_))));
+    }
+
+    #[test]
+    fn run_oversized_input() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+        let input = "1 + ".repeat(300_000) + "1";
+
+        match session.run(&input) {
+            Err(EvalError::Parse(err)) => {
+                assert_eq!(err.error, "Input too large.");
+                assert_eq!(err.index, DEFAULT_MAX_INPUT_LEN);
+            }
+            result => panic!("{:?}", result),
+        }
+
+        session.max_input_len = 9;
+
+        assert_eq!(session.run("1 + 2 + 3").unwrap(), Some(6.0));
+        assert_eq!(session.run_program("1 + 2; 3 + 4").unwrap(), Some(7.0));
+
+        match session.run("1 + 2 + é") {
+            Err(EvalError::Parse(err)) => {
+                assert_eq!(err.index, 8);
+                assert_eq!(err.span, 8..10);
+            }
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn run_percent_mode() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        assert!(matches!(session.run("50%"), Err(EvalError::Parse(_))));
+
+        session.percent = true;
+
+        assert_eq!(session.run("50%").unwrap(), Some(0.5));
+        assert_eq!(session.run("200 * 50%").unwrap(), Some(100.0));
+        assert_eq!(session.run("x = 40").unwrap(), None);
+        assert_eq!(session.run("x% + 10 % 4").unwrap(), Some(2.4));
+        assert_eq!(session.run("(1 + 1)% - 1").unwrap(), Some(0.02 - 1.0));
+        assert_eq!(session.run("17 % (5)").unwrap(), Some(2.0));
+    }
+
+    #[test]
+    fn run_strict_mode() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        assert_eq!(session.run("007 + 1").unwrap(), Some(8.0));
+        assert_eq!(session.run("１＋２").unwrap(), Some(3.0));
+
+        session.strict = true;
+
+        match session.run("1 + 007") {
+            Err(EvalError::Parse(err)) => {
+                assert_eq!(err.error, "Leading zero in decimal literal in strict mode.");
+                assert_eq!(err.span, 4..7);
+            }
+            result => panic!("{:?}", result),
+        }
+
+        assert!(matches!(session.run("１＋２"), Err(EvalError::Parse(_))));
+        assert!(matches!(session.run("1 € 2"), Err(EvalError::Parse(_))));
+        assert_eq!(session.run("7 + 0.5 + 0x07 # ０７").unwrap(), Some(14.5));
+    }
+
+    #[test]
+    fn run_large_numbers() {
+        // decimal literals and arithmetic are floating-point, so neither overflows 64 bits
+        assert_eq!(eval_all(&["10000000000000000000"]).unwrap(), Some(1e19));
+        assert_eq!(
+            eval_all(&["5000000000 * 5000000000"]).unwrap(),
+            Some(2.5e19)
+        );
+        assert_eq!(eval_all(&["2 ** 1024"]).unwrap(), Some(f64::INFINITY));
+        assert!(matches!(
+            eval_all(&["0x1_0000_0000_0000_0000"]),
+            Err(EvalError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn run_division_by_runtime_zero() {
+        // values are floats, so dividing by zero does not trap
+        assert_eq!(eval_all(&["x = 0", "10 / x"]).unwrap(), Some(f64::INFINITY));
+        assert_eq!(
+            eval_all(&["x = 0", "-10 // x"]).unwrap(),
+            Some(f64::NEG_INFINITY)
+        );
+        assert!(eval_all(&["x = 0", "10 % x"]).unwrap().unwrap().is_nan());
+        assert!(eval_all(&["x = 0", "x / x"]).unwrap().unwrap().is_nan());
+    }
+
+    #[test]
+    fn run_persists_globals() {
+        assert_eq!(eval_all(&["x = 5", "x * 2"]).unwrap(), Some(10.0));
+        assert_eq!(
+            eval_all(&["x = 5", "def f(a) a + x", "f(1)"]).unwrap(),
+            Some(6.0)
+        );
+        assert!(matches!(eval_all(&["y"]), Err(EvalError::Compile(_))));
+    }
+
+    #[test]
+    fn run_assigns_then_reads_variables() {
+        assert_eq!(eval_all(&["x = 5", "x"]).unwrap(), Some(5.0));
+        assert_eq!(eval_all(&["x = 5", "x = x + 1", "x"]).unwrap(), Some(6.0));
+        assert!(matches!(
+            eval_all(&["x = 5", "y + 1"]),
+            Err(EvalError::Compile("Could not find a matching variable."))
+        ));
+    }
+
+    #[test]
+    fn run_stores_last_result() {
+        assert_eq!(eval_all(&["2 + 2", "_ * 10"]).unwrap(), Some(40.0));
+        assert_eq!(eval_all(&["2 + 2", "1 + )", "_"]).unwrap(), Some(4.0));
+        assert_eq!(eval_all(&["2 + 2", "def f(a) a", "_"]).unwrap(), Some(4.0));
+        assert!(matches!(eval_all(&["_"]), Err(EvalError::Compile(_))));
+    }
+
+    #[test]
+    fn run_discarding_assignments() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        // assigning '_' discards the value, and reading it still gives the last result
+        assert_eq!(session.run("2 + 2").unwrap(), Some(4.0));
+        assert_eq!(session.run_program("_ = 5; _").unwrap(), Some(4.0));
+
+        assert_eq!(eval_all(&["_ = 5"]).unwrap(), None);
+        assert_eq!(
+            eval_all(&["x = 1", "2", "_ = (x = 3)", "x + _"]).unwrap(),
+            Some(5.0)
+        );
+    }
+
+    #[test]
+    fn run_assignments_and_trailing_semicolons() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        assert_eq!(session.run_program("x = 5").unwrap(), None);
+        assert_eq!(session.run_program("x + 1").unwrap(), Some(6.0));
+        assert_eq!(session.run_program("x + 2;").unwrap(), None);
+        assert_eq!(session.run_program("x + 3; # three").unwrap(), None);
+
+        // assignments and silenced expressions still run
+        assert_eq!(session.run_program("_").unwrap(), Some(8.0));
+        assert_eq!(session.run_program("x = x * 2; x").unwrap(), Some(10.0));
+    }
+
+    #[test]
+    fn run_reuses_engine() {
+        let inputs = [
+            "def f(a) a * 2",
+            "f(1)",
+            "def g(a) f(a) + 1",
+            "g(2)",
+            "f(3) + g(4)",
+        ];
+
+        assert_eq!(eval_all(&inputs).unwrap(), Some(15.0));
+        assert_eq!(
+            eval_all(&["def f(a) a", "f(1)", "f(2)", "f(3)"]).unwrap(),
+            Some(3.0)
+        );
+    }
+
+    #[test]
+    fn run_many_inputs() {
+        // temporaries are named within the function being compiled, and each input
+        // gets a module and an anonymous function name of its own, so none may clash
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        session.run("def twice(a) var t = a * 2 in t").unwrap();
+        session.run("x = 1").unwrap();
+
+        for i in 0..500 {
+            let input = format!(
+                "twice({0}) + x * ({0} > 250) + (_ if {0} > 0 else 0) * 0",
+                i
+            );
+
+            assert_eq!(
+                session.run(&input).unwrap(),
+                Some((2 * i + (i > 250) as i32) as f64)
+            );
+        }
+    }
+
+    #[test]
+    fn run_with_optimization_levels() {
+        let inputs = [
+            "def fib(n) if n < 2 then n else fib(n - 1) + fib(n - 2)",
+            "x = 7",
+            "fib(x + 3) * 2 - x / 4",
+        ];
+        let levels = [
+            OptimizationLevel::None,
+            OptimizationLevel::Less,
+            OptimizationLevel::Default,
+            OptimizationLevel::Aggressive,
+        ];
+
+        for level in levels {
+            let context = Context::create();
+            let mut session = Session::with_optimization_level(&context, level);
+            let mut result = None;
+
+            for input in inputs {
+                result = session.run(input).unwrap();
+            }
+
+            assert_eq!(result, Some(108.25), "{:?}", level);
+        }
+    }
+
+    #[test]
+    fn run_program_statements() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        assert_eq!(
+            session.run_program("x = 2; y = 3; x + y").unwrap(),
+            Some(5.0)
+        );
+        assert_eq!(session.run_program(";x * y").unwrap(), Some(6.0));
+        assert_eq!(session.run_program("x;; ;y").unwrap(), Some(3.0));
+        assert_eq!(session.run_program(";x * y;; ").unwrap(), None);
+        assert_eq!(session.run_program("x * y;").unwrap(), None);
+        assert_eq!(session.run_program("z = 7").unwrap(), None);
+        assert_eq!(session.run_program("z + 1").unwrap(), Some(8.0));
+        assert_eq!(
+            session.run_program("def f(a) a * x; f(y)").unwrap(),
+            Some(6.0)
+        );
+        assert_eq!(session.run_program("def g(a) a").unwrap(), None);
+        assert_eq!(session.run_program(" ; ").unwrap(), None);
+        assert_eq!(session.run_program("").unwrap(), None);
+
+        match session.run_program("x = 4; x + 0xGG") {
+            Err(EvalError::Parse(err)) => {
+                assert_eq!(err.index, 10);
+                assert_eq!(err.span, 10..14);
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // statements before an error still ran
+        assert_eq!(session.run_program("x").unwrap(), Some(4.0));
+    }
+
+    #[test]
+    fn clear_forgets_definitions() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        for input in [
+            "x = 5",
+            "def f(a) a + x",
+            "def binary @ 5 (a, b) a - b",
+            "f(1) @ 2",
+        ] {
+            session.run(input).unwrap();
+        }
+
+        session.clear();
+
+        assert!(matches!(session.run("x"), Err(EvalError::Compile(_))));
+        assert!(matches!(session.run("f(1)"), Err(EvalError::Compile(_))));
+        assert!(matches!(session.run("_"), Err(EvalError::Compile(_))));
+        // unknown binary operators still parse, but no longer compile
+        assert!(matches!(
+            session.run("1 @ 2"),
+            Err(EvalError::Compile("Undefined binary operator."))
+        ));
+        assert_eq!(session.run_program("x = 2; x * 3").unwrap(), Some(6.0));
+    }
+
+    #[test]
+    fn ir_does_not_run() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        let ir = session.ir("2 + 3 * 4").unwrap();
+
+        assert!(ir.contains("define double @anonymous"));
+        assert!(ir.contains("ret double 1.400000e+01"));
+
+        session.ir("x = 5").unwrap();
+
+        assert!(matches!(session.run("x"), Err(EvalError::Compile(_))));
+    }
+
+    /// Returns a pseudo-random number lower than `bound`, advancing the given seed.
+    fn next_random(seed: &mut u64, bound: u64) -> u64 {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+
+        (*seed >> 33) % bound
+    }
+
+    /// Generates a pseudo-random expression made of literals and built-in operators.
+    fn random_expr(seed: &mut u64, depth: usize) -> String {
+        const OPS: &[&str] = &[
+            "+", "-", "*", "/", "//", "%", "**", "<", ">", "<=", ">=", "==", "!=", "&", "|", "^",
+            "<<", ">>",
+        ];
+
+        match next_random(seed, if depth == 0 { 2 } else { 5 }) {
+            0 => format!("{}", next_random(seed, 10)),
+            1 => format!("{}.5", next_random(seed, 10)),
+            2 => format!("-({})", random_expr(seed, depth - 1)),
+            _ => {
+                let op = OPS[next_random(seed, OPS.len() as u64) as usize];
+
+                format!(
+                    "({} {} {})",
+                    random_expr(seed, depth - 1),
+                    op,
+                    random_expr(seed, depth - 1)
+                )
+            }
+        }
+    }
+
+    #[test]
+    fn const_eval_matches_compiled() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+        let mut seed = 42;
+
+        for _ in 0..500 {
+            let input = random_expr(&mut seed, 3);
+            let fun = session.parse(&input).unwrap();
+
+            let Some(folded) = session.try_const_eval(fun.body.as_ref().unwrap()) else {
+                continue;
+            };
+
+            let compiled = session.execute(fun).unwrap().unwrap();
+
+            assert!(
+                folded == compiled || (folded.is_nan() && compiled.is_nan()),
+                "{} folded to {} but ran to {}",
+                input,
+                folded,
+                compiled
+            );
+        }
+    }
+
+    /// Runs the given inputs in order in a compiling and an interpreting session,
+    /// and checks that both agree on every result.
+    fn assert_backends_agree(inputs: &[&str]) {
+        let context = Context::create();
+        let mut compiled = Session::new(&context);
+        let mut interpreted = Session::new(&context);
+
+        compiled.seed(7);
+        interpreted.seed(7);
+        interpreted.interpret = true;
+
+        for input in inputs {
+            match (compiled.run(input), interpreted.run(input)) {
+                (Ok(Some(expected)), Ok(Some(actual))) => assert!(
+                    expected == actual || (expected.is_nan() && actual.is_nan()),
+                    "{} ran to {} but was interpreted to {}",
+                    input,
+                    expected,
+                    actual
+                ),
+                (Ok(None), Ok(None)) => (),
+                (Err(EvalError::Compile(expected)), Err(EvalError::Compile(actual))) => {
+                    assert_eq!(expected, actual, "{}", input)
+                }
+                (expected, actual) => panic!(
+                    "{} ran to {:?} but was interpreted to {:?}",
+                    input, expected, actual
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn interpreter_matches_compiled() {
+        assert_backends_agree(&[
+            "_ + 1",
+            "def fib(n) if n < 2 then n else fib(n - 1) + fib(n - 2)",
+            "fib(15)",
+            "x = 3",
+            "x * 2",
+            "_ + 1",
+            "def binary~ 30 (a, b) a * 10 + b",
+            "1 ~ 2 ~ 3",
+            "var a = 1, b = 2 in a + b * (var a = 10 in a)",
+            "def sum(n) var s = 0 in (for i = 1, i <= n in s = s + i) + s",
+            "sum(100)",
+            "y = 0",
+            "for i = 0, i < 5, 2 in y = y + i",
+            "y",
+            "_ = y * 2",
+            "_ + 1",
+            "def f(_) (_ = _ + 1) * _",
+            "f(2)",
+            "round(-2.5) + floor(-2.1) * 10 + ceil(-0.5) * 100",
+            "pi * e + (var e = 2 in e ** 2) // pi",
+            "e = 1",
+            "5! + isqrt(99) + gcd(-12, 18) + abs(-2)",
+            "isqrt(4503599761588224)",
+            "max(1, 0 / 0) + min(2, 3)",
+            "wmul(2 ** 40, 2 ** 40) + wsub(1, 2)",
+            "1 and 0 or not 0",
+            "2 < x < 4 == 1",
+            "rand(100) + rand(10) * 100",
+            "extern sin(a)",
+            "sin(1)",
+            "(1.5 | 2) << 3",
+            "1e19 & 1",
+            "(0 / 0) | 0",
+            "-1e19 ^ 1",
+            "wadd(9223372036854775807, 1)",
+            "-7 // 2 + 7 % -3",
+            "def shl(a, b) a << b",
+            "shl(1, 3) + shl(1, 64)",
+            "shl(-8, -1)",
+            "1 << -1",
+            "def unary-(a) a",
+            "-2",
+            "undefined + 1",
+            "nope(1)",
+            "1 % 0",
+            "1 % (1 == 2)",
+            "1 % (1 & 2)",
+            "1 % not 1",
+            "1 % (5 % 5)",
+            "1 << (2 < 1) - 1",
+            "isqrt(-(1 < 2))",
+            "(0 - 3)!",
+            "isqrt(-4)",
+            "max(1)",
+            "2 = 3",
+        ]);
+    }
+
+    #[test]
+    fn interpreter_matches_compiled_on_random_exprs() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+        let mut seed = 7;
+
+        for _ in 0..500 {
+            let input = random_expr(&mut seed, 3);
+            let fun = session.parse(&input).unwrap();
+
+            // skip the expressions whose compiled value is undefined, such as shifts
+            // by more than 64 bits
+            if session.try_const_eval(fun.body.as_ref().unwrap()).is_none() {
+                continue;
+            }
+
+            let interpreted = interpret(fun.body.as_ref().unwrap(), &HashMap::new()).unwrap();
+            let compiled = session.execute(fun).unwrap().unwrap();
+
+            assert!(
+                interpreted == compiled || (interpreted.is_nan() && compiled.is_nan()),
+                "{} was interpreted to {} but ran to {}",
+                input,
+                interpreted,
+                compiled
+            );
+        }
+    }
+
+    #[test]
+    fn const_eval_skips_variables_and_functions() {
+        let context = Context::create();
+        let mut session = Session::new(&context);
+
+        session.run("def unary-(a) a").unwrap();
+
+        let fun = session.parse("x + 1").unwrap();
+        assert_eq!(session.try_const_eval(fun.body.as_ref().unwrap()), None);
+
+        let fun = session.parse("-2").unwrap();
+        assert_eq!(session.try_const_eval(fun.body.as_ref().unwrap()), None);
+        assert_eq!(session.run("-2").unwrap(), Some(2.0));
+
+        let fun = session.parse("1 % 0").unwrap();
+        assert_eq!(session.try_const_eval(fun.body.as_ref().unwrap()), None);
+    }
+
+    #[test]
+    fn run_definition() {
+        assert_eq!(eval_all(&["def f(a) a"]).unwrap(), None);
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use inkwell::context::Context;
+use sinoc_llvm::{check, eval, eval_lines, eval_with_vars, EvalError};
+
+#[test]
+fn eval_expression() {
+    assert_eq!(eval("1 + 2 * 3").unwrap(), 7.0);
+    assert!(matches!(eval("def f(a) a"), Err(EvalError::Parse(_))));
+}
+
+#[test]
+fn eval_expression_with_vars() {
+    let vars = HashMap::from([("x".to_string(), 10.0), ("y".to_string(), 0.5)]);
+
+    assert_eq!(eval_with_vars("x * 2", &vars).unwrap(), 20.0);
+    assert_eq!(eval_with_vars("(x = 3) + x * y", &vars).unwrap(), 4.5);
+    assert_eq!(eval_with_vars("x", &vars).unwrap(), 10.0);
+    assert!(matches!(
+        eval_with_vars("x + z", &vars),
+        Err(EvalError::Compile(_))
+    ));
+    assert!(matches!(
+        eval_with_vars("def f(a) a", &vars),
+        Err(EvalError::Parse(_))
+    ));
+}
+
+#[test]
+fn check_syntax() {
+    assert!(check("1+2").is_ok());
+    assert!(check("def f(a) a * undefined(a)").is_ok());
+    assert!(matches!(check("1+"), Err(EvalError::Parse(_))));
+    assert!(matches!(check("(1 + 2"), Err(EvalError::Parse(_))));
+}
+
+#[test]
+fn eval_lines_in_order() {
+    let context = Context::create();
+    let results = eval_lines(
+        &context,
+        [
+            "x = 4",
+            "x * 2",
+            "1 +",
+            "def f(a) a - x",
+            "f(10)",
+            "y",
+            "_ + 1",
+        ],
+    );
+
+    assert_eq!(results.len(), 7);
+    assert_eq!(results[0].as_ref().unwrap(), &None);
+    assert_eq!(results[1].as_ref().unwrap(), &Some(8.0));
+    assert!(matches!(results[2], Err(EvalError::Parse(_))));
+    assert_eq!(results[3].as_ref().unwrap(), &None);
+    assert_eq!(results[4].as_ref().unwrap(), &Some(6.0));
+    assert!(matches!(results[5], Err(EvalError::Compile(_))));
+    assert_eq!(results[6].as_ref().unwrap(), &Some(7.0));
+}
@@ -0,0 +1,164 @@
+use std::process::{Command, Output};
+
+/// Runs the REPL executable with the given arguments.
+fn run_with_args(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_sinoc_llvm"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn version_flag() {
+    let output = run_with_args(&["--version"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.starts_with(&format!("Sino {} ", env!("CARGO_PKG_VERSION"))));
+    assert!(stdout.trim_end().ends_with(std::env::consts::OS));
+}
+
+#[test]
+fn help_flag() {
+    let output = run_with_args(&["--help"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert!(stdout.starts_with("Usage: sino [options] [script]"));
+    assert!(stdout.contains("--emit-obj <path>"));
+}
+
+#[test]
+fn interp_flag() {
+    let output = run_with_args(&["--interp", "-e", "def f(a) a * 2", "-e", "f(7)"]);
+
+    assert_eq!(output.status.code(), Some(14));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "14\n");
+}
+
+#[test]
+fn trace_flag() {
+    let output = run_with_args(&["--trace", "-e", "2 + 3 * 4"]);
+
+    assert_eq!(output.status.code(), Some(14));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "3 * 4 = 12\n2 + 12 = 14\n14\n"
+    );
+}
+
+#[test]
+fn prompt_flag() {
+    let output = run_with_args(&["--help"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(stdout.contains("--prompt <text>"));
+    assert!(stdout.contains("SINO_PROMPT"));
+
+    let output = run_with_args(&["--prompt"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Expected a prompt after '--prompt'.\n"
+    );
+}
+
+#[test]
+fn json_flag() {
+    let output = run_with_args(&["--json", "-e", "def f(a) a * 2", "-e", "f(4)"]);
+
+    assert_eq!(output.status.code(), Some(8));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "{\"ok\":true}\n{\"ok\":true,\"value\":8}\n"
+    );
+
+    let output = run_with_args(&["--json", "-e", "1", "-e", "y"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stderr.is_empty());
+    assert!(stdout.starts_with("{\"ok\":true,\"value\":1}\n{\"ok\":false,\"error\":\"Error "));
+    assert!(stdout.ends_with(",\"kind\":\"Compile\",\"line\":2}\n"));
+}
+
+#[test]
+fn expr_environment_variable() {
+    let run_with_expr = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_sinoc_llvm"))
+            .args(args)
+            .env("SINO_EXPR", "x = 6; x * 7")
+            .output()
+            .unwrap()
+    };
+
+    let output = run_with_expr(&[]);
+
+    assert_eq!(output.status.code(), Some(42));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "42\n");
+
+    let output = run_with_expr(&["-e", "1 + 2"]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+}
+
+#[test]
+fn unknown_flag() {
+    let output = run_with_args(&["--bogus"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("'--bogus'"));
+}
+
+#[test]
+fn opt_flag() {
+    let output = run_with_args(&["--opt", "aggressive", "--help"]);
+
+    assert_eq!(output.status.code(), Some(0));
+
+    let output = run_with_args(&["--opt", "fastest"]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("'--opt'"));
+}
+
+#[test]
+fn eval_flag() {
+    let output = run_with_args(&["-e", "2 + 3 * 4"]);
+
+    assert_eq!(output.status.code(), Some(14));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "14\n");
+
+    let output = run_with_args(&["-e", "x = 2", "-e", "x * 5"]);
+
+    assert_eq!(output.status.code(), Some(10));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "10\n");
+}
+
+#[test]
+fn eval_flag_error() {
+    let output = run_with_args(&["-e", "1", "-e", "1 +", "-e", "2"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("-e:2: "));
+}
+
+#[test]
+fn quiet_flag() {
+    for flag in ["-q", "--quiet"] {
+        let output = run_with_args(&[flag, "-e", "1 + 1"]);
+
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+    }
+}
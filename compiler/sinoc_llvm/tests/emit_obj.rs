@@ -0,0 +1,60 @@
+use std::env;
+use std::fs;
+use std::process::Command;
+
+#[test]
+fn emit_obj_writes_object_file() {
+    let path = env::temp_dir().join("sino_emit_obj_writes_object_file.o");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_sinoc_llvm"))
+        .arg("--emit-obj")
+        .arg(&path)
+        .arg("2 + 3")
+        .status()
+        .unwrap();
+
+    let len = fs::metadata(&path).unwrap().len();
+
+    fs::remove_file(&path).unwrap();
+
+    assert!(status.success());
+    assert!(len > 0);
+}
+
+#[test]
+fn emit_llvm_writes_ir_file() {
+    let path = env::temp_dir().join("sino_emit_llvm_writes_ir_file.ll");
+
+    let status = Command::new(env!("CARGO_BIN_EXE_sinoc_llvm"))
+        .arg("--emit-llvm")
+        .arg(&path)
+        .arg("2 + 3")
+        .status()
+        .unwrap();
+
+    let ir = fs::read_to_string(&path).unwrap();
+
+    fs::remove_file(&path).unwrap();
+
+    assert!(status.success());
+    assert!(ir.contains("define double @anonymous"));
+    assert!(ir.contains("target triple"));
+}
+
+#[test]
+fn emit_obj_reports_errors() {
+    let path = env::temp_dir().join("sino_emit_obj_reports_errors.o");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sinoc_llvm"))
+        .arg("--emit-obj")
+        .arg(&path)
+        .arg("2 + )")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!path.exists());
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("Error parsing expression"));
+}
@@ -0,0 +1,48 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Runs the REPL executable with the given piped standard input.
+fn run_piped(input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sinoc_llvm"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn piped_input_runs_quietly() {
+    let output = run_piped("1 + 2\n\nx = 4 # four\nx * 2\n");
+
+    assert_eq!(output.status.code(), Some(8));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n8\n");
+}
+
+#[test]
+fn piped_input_stops_on_error() {
+    let output = run_piped("1\n2 + )\n3\n");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .starts_with("<stdin>:2: "));
+}
+
+#[test]
+fn piped_input_runs_statements() {
+    let output = run_piped("x = 2; y = 3; x + y\nx * y;\n");
+
+    assert_eq!(output.status.code(), Some(5));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "5\n");
+}
@@ -0,0 +1,125 @@
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+/// Writes `source` to a temporary script, and runs the REPL executable on it with
+/// the given options and environment variables.
+fn run_script_with(name: &str, source: &str, args: &[&str], vars: &[(&str, &str)]) -> Output {
+    let path = env::temp_dir().join(name);
+
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_sinoc_llvm"))
+        .args(args)
+        .arg(&path)
+        .envs(vars.iter().copied())
+        .output()
+        .unwrap();
+
+    fs::remove_file(&path).unwrap();
+
+    output
+}
+
+/// Writes `source` to a temporary script, and runs the REPL executable on it.
+fn run_script(name: &str, source: &str) -> Output {
+    run_script_with(name, source, &[], &[])
+}
+
+#[test]
+fn script_prints_results() {
+    let output = run_script(
+        "sino_script_prints_results.sn",
+        "# doubles its argument\ndef f(a) a * 2\n\n1 + 2 # three\nx = f(4)\nx - 1\n",
+    );
+
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n7\n");
+}
+
+#[test]
+fn script_with_crlf_line_endings() {
+    let output = run_script(
+        "sino_script_with_crlf_line_endings.sn",
+        "# doubles its argument\r\ndef f(a) a * 2\r\n\r\nf(1 + 2)\r\nf(4)\r\n",
+    );
+
+    assert_eq!(output.status.code(), Some(8));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "6\n8\n");
+}
+
+#[test]
+fn script_with_block_comments() {
+    let output = run_script(
+        "sino_script_with_block_comments.sn",
+        "/* doubles\n   its argument */\ndef f(a) a * /* two */ 2\nf(3) /* ; */ + 1\n",
+    );
+
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "7\n");
+}
+
+#[test]
+fn script_returns_early() {
+    let output = run_script(
+        "sino_script_returns_early.sn",
+        "def f(a) a * 2\nf(1)\nreturn f(3) + 1\nf(4)\nunknown(\n",
+    );
+
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n7\n");
+
+    let output = run_script("sino_script_returns_nothing.sn", "7\nreturn\n8\n");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "7\n");
+}
+
+#[test]
+fn script_exit_code() {
+    let exit_code = |name, source| run_script(name, source).status.code();
+
+    assert_eq!(exit_code("sino_exit_code_0.sn", "def f(a) a\n"), Some(0));
+    assert_eq!(exit_code("sino_exit_code_1.sn", "3.9\n"), Some(3));
+    assert_eq!(exit_code("sino_exit_code_2.sn", "256 + 44\n"), Some(44));
+    assert_eq!(exit_code("sino_exit_code_3.sn", "-1\n"), Some(255));
+}
+
+#[test]
+fn script_stops_on_error() {
+    let output = run_script("sino_script_stops_on_error.sn", "1\n2 + )\n3\n");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "1\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains(":2: "));
+}
+
+#[test]
+fn script_keeps_going_after_error() {
+    let output = run_script_with(
+        "sino_script_keeps_going_after_error.sn",
+        "x = 1\n2 + )\nx + 2\n",
+        &["--keep-going"],
+        &[],
+    );
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "3\n");
+    assert!(String::from_utf8(output.stderr).unwrap().contains(":2: "));
+}
+
+#[test]
+fn script_errors_without_color() {
+    let output = run_script_with(
+        "sino_script_errors_without_color.sn",
+        "1 +\n",
+        &[],
+        &[("NO_COLOR", "1")],
+    );
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.contains(":1: Error parsing expression"));
+    assert!(!stderr.contains('\x1b'));
+}